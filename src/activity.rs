@@ -0,0 +1,101 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+// How far back messages count towards a channel's speed
+const WINDOW_SECS: i64 = 3600;
+// Don't trust speeds measured over less time than this
+const MIN_SPAN_SECS: i64 = 600;
+
+// Replaces the flat proc chance with one that aims for a number of replies per hour
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct AdaptiveRate {
+    pub replies_per_hour: u32,
+    // Seconds between unsolicited replies
+    pub cooldown: i64,
+}
+
+// Recent messages in a channel, not backed up
+#[derive(Clone, Default)]
+pub struct ChannelActivity {
+    messages: VecDeque<i64>,
+    last_reply: Option<i64>,
+}
+
+impl ChannelActivity {
+    pub fn record_message(&mut self, now: i64) {
+        self.messages.push_back(now);
+
+        while self.messages.front().is_some_and(|t| now - t > WINDOW_SECS) {
+            self.messages.pop_front();
+        }
+    }
+
+    pub fn record_reply(&mut self, now: i64) {
+        self.last_reply = Some(now);
+    }
+
+    pub fn messages_per_hour(&self, now: i64) -> f64 {
+        let first = match self.messages.front() {
+            None => return 0.0,
+            Some(t) => *t,
+        };
+
+        let span = (now - first).clamp(MIN_SPAN_SECS, WINDOW_SECS);
+        self.messages.len() as f64 * 3600.0 / span as f64
+    }
+
+    // Chance for a single message to get a reply
+    pub fn reply_chance(&self, rate: &AdaptiveRate, now: i64) -> f64 {
+        if self.last_reply.is_some_and(|t| now - t < rate.cooldown) {
+            return 0.0;
+        }
+
+        let per_hour = self.messages_per_hour(now).max(1.0);
+        (rate.replies_per_hour as f64 / per_hour).min(1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: AdaptiveRate = AdaptiveRate { replies_per_hour: 6, cooldown: 300 };
+
+    #[test]
+    fn busier_channels_get_lower_chances() {
+        let mut slow = ChannelActivity::default();
+        let mut busy = ChannelActivity::default();
+
+        for i in 0..60 {
+            busy.record_message(i * 60);
+            if i % 10 == 0 {
+                slow.record_message(i * 60);
+            }
+        }
+
+        assert!((busy.reply_chance(&RATE, 3600) - 0.1).abs() < 0.01);
+        assert_eq!(slow.reply_chance(&RATE, 3600), 1.0);
+    }
+
+    #[test]
+    fn cooldown_blocks_replies() {
+        let mut activity = ChannelActivity::default();
+        activity.record_message(0);
+        activity.record_reply(0);
+
+        assert_eq!(activity.reply_chance(&RATE, 299), 0.0);
+        assert!(activity.reply_chance(&RATE, 300) > 0.0);
+    }
+
+    #[test]
+    fn forgets_old_messages() {
+        let mut activity = ChannelActivity::default();
+        for i in 0..100 {
+            activity.record_message(i);
+        }
+        activity.record_message(10_000);
+
+        assert_eq!(activity.messages.len(), 1);
+    }
+}
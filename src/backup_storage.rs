@@ -0,0 +1,177 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use async_std::fs;
+use poise::serenity_prelude::*;
+
+use crate::Error;
+
+pub type BackupStorageRef = Arc<dyn BackupStorage + Send + Sync>;
+
+#[async_trait]
+pub trait BackupStorage: Send + Sync {
+    async fn save(&self, data: Vec<u8>) -> Result<(), Error>;
+    async fn load_latest(&self) -> Result<Option<Vec<u8>>, Error>;
+}
+
+// Both backends keep this many of the newest backups and delete the rest
+const KEEP_BACKUPS: usize = 5;
+
+// DEFINITIONS
+pub struct DiscordChannelStorage {
+    pub http: Arc<Http>,
+    pub channel_id: ChannelId,
+}
+
+pub struct LocalDirStorage {
+    pub dir: PathBuf,
+}
+
+// IMPLEMENTATIONS
+#[async_trait] // Uploads backups as attachments to a channel
+impl BackupStorage for DiscordChannelStorage {
+    async fn save(&self, data: Vec<u8>) -> Result<(), Error> {
+        self.channel_id
+            .send_files(
+                &self.http,
+                vec![CreateAttachment::bytes(data, format!("{}.cbor", Timestamp::now()))],
+                Default::default(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn load_latest(&self) -> Result<Option<Vec<u8>>, Error> {
+        let messages = self.channel_id.messages(&self.http, GetMessages::default()).await?;
+
+        // We don't care
+        if messages.is_empty() {
+            eprintln!("No messages in backup channel!");
+            return Ok(None);
+        }
+
+        // Delete old backups, newest come first
+        for del in messages.iter().skip(KEEP_BACKUPS) {
+            if let Err(e) = del.delete(&self.http).await {
+                eprintln!("FAILED TO DELETE OLD BACKUP {}: {}", del.id, e);
+            }
+        }
+
+        // Load last backup
+        let use_backup = &messages[0];
+
+        // We don't care
+        if use_backup.attachments.is_empty() {
+            eprintln!("Last message in backup channel didn't have a file!");
+            return Ok(None);
+        }
+
+        Ok(Some(use_backup.attachments[0].download().await?))
+    }
+}
+
+impl LocalDirStorage {
+    // Backups are named after the unix timestamp they were taken at
+    async fn backup_files(&self) -> Result<Vec<(i64, PathBuf)>, Error> {
+        let mut files = Vec::new();
+
+        let mut entries = fs::read_dir(&self.dir).await?;
+        while let Some(entry) = async_std::stream::StreamExt::next(&mut entries).await {
+            let path: PathBuf = entry?.path().into();
+
+            if path.extension().is_none_or(|e| e != "cbor") {
+                continue;
+            }
+
+            if let Some(time) = path.file_stem().and_then(|s| s.to_str()).and_then(|s| s.parse::<i64>().ok()) {
+                files.push((time, path));
+            }
+        }
+
+        // Newest first
+        files.sort_by_key(|f| std::cmp::Reverse(f.0));
+        Ok(files)
+    }
+}
+
+#[async_trait] // Writes backups as timestamped files to a local directory
+impl BackupStorage for LocalDirStorage {
+    async fn save(&self, data: Vec<u8>) -> Result<(), Error> {
+        fs::create_dir_all(&self.dir).await?;
+
+        // Always after the newest backup, even when saving twice in a second
+        let newest = self.backup_files().await?.first().map(|f| f.0 + 1);
+        let time = Timestamp::now().unix_timestamp().max(newest.unwrap_or(i64::MIN));
+
+        let tmp_path = self.dir.join(format!("{}.cbor.tmp", time));
+        let path = self.dir.join(format!("{}.cbor", time));
+
+        // Write then rename so a crash never leaves a half written backup behind
+        fs::write(&tmp_path, data).await?;
+        fs::rename(&tmp_path, &path).await?;
+
+        // Delete old backups
+        for (_, old) in self.backup_files().await?.into_iter().skip(KEEP_BACKUPS) {
+            if let Err(e) = fs::remove_file(&old).await {
+                eprintln!("FAILED TO DELETE OLD BACKUP {}: {}", old.display(), e);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn load_latest(&self) -> Result<Option<Vec<u8>>, Error> {
+        if !fs::metadata(&self.dir).await.is_ok_and(|m| m.is_dir()) {
+            eprintln!("No backup directory at {}!", self.dir.display());
+            return Ok(None);
+        }
+
+        match self.backup_files().await?.first() {
+            None => {
+                eprintln!("No backups in backup directory!");
+                Ok(None)
+            }
+            Some((_, path)) => Ok(Some(fs::read(path).await?)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn storage(name: &str) -> LocalDirStorage {
+        let dir = std::env::temp_dir().join(format!("scluner_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        LocalDirStorage { dir }
+    }
+
+    #[test]
+    fn keeps_the_newest_backups() {
+        let storage = storage("backups");
+
+        async_std::task::block_on(async {
+            assert_eq!(storage.load_latest().await.unwrap(), None);
+
+            for i in 0..KEEP_BACKUPS as u8 + 3 {
+                storage.save(vec![i]).await.unwrap();
+            }
+            // Half written backups get skipped
+            fs::write(storage.dir.join("99999999999.cbor.tmp"), [0]).await.unwrap();
+
+            assert_eq!(storage.load_latest().await.unwrap(), Some(vec![KEEP_BACKUPS as u8 + 2]));
+            assert_eq!(storage.backup_files().await.unwrap().len(), KEEP_BACKUPS);
+        });
+
+        let names: Vec<String> = std::fs::read_dir(&storage.dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        std::fs::remove_dir_all(&storage.dir).unwrap();
+
+        // Named after when they were taken, nothing left mid rename
+        assert_eq!(names.iter().filter(|n| n.ends_with(".cbor.tmp")).count(), 1);
+        assert!(names.iter().filter(|n| n.ends_with(".cbor")).all(|n| n.trim_end_matches(".cbor").parse::<i64>().is_ok()));
+    }
+}
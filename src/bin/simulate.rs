@@ -0,0 +1,332 @@
+// Replays a chat log through the bot without touching discord and prints what it would've done
+//
+// simulate [--backup FILE] [--guild ID] [--proc N] [--out-of N] [--mutators A,B] [--emojis A,B] [--no-learn]
+//          [--seed N] [--replay SEED [--trigger TEXT]] [LOG]
+//
+// LOG is either a JSON array of {"author", "content", "mention"} objects or text with one
+// "author: content" message per line, mentioning @bot in a line pings the bot
+// Without a LOG the backup's stored messages for the guild get replayed instead
+// --seed makes the whole run reproducible, --replay makes the one reply with a logged "Reply seed"
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::sync::Arc;
+
+use poise::serenity_prelude::*;
+use serde::Deserialize;
+
+use ogscluner::backup_storage::LocalDirStorage;
+use ogscluner::memory_transport::{MemoryTransport, TransportEvent};
+use ogscluner::mutators::DefinedMutators;
+use ogscluner::scluner_backup::SclunerBackup;
+use ogscluner::{Error, SclunerInstance};
+
+const BOT: UserId = UserId::new(1);
+const CHANNEL: ChannelId = ChannelId::new(2);
+const DEFAULT_GUILD: GuildId = GuildId::new(3);
+
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+struct LogEntry {
+    author: String,
+    content: String,
+    #[serde(default)]
+    mention: bool,
+}
+
+#[derive(Default)]
+struct Options {
+    backup: Option<PathBuf>,
+    guild: Option<GuildId>,
+    proc: Option<u32>,
+    out_of: Option<u32>,
+    mutators: Option<Vec<DefinedMutators>>,
+    emojis: Vec<ReactionType>,
+    no_learn: bool,
+    seed: Option<u64>,
+    replay: Option<u64>,
+    trigger: String,
+    log: Option<PathBuf>,
+}
+
+fn parse_mutator(name: &str) -> Result<DefinedMutators, Error> {
+    DefinedMutators::parse(name)
+        .ok_or_else(|| format!("unknown mutator {}", name).into())
+}
+
+fn parse_guild(id: &str) -> Result<GuildId, Error> {
+    match id.parse::<u64>()? {
+        0 => Err("guild id can't be 0".into()),
+        id => Ok(GuildId::new(id)),
+    }
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options, Error> {
+    let mut options = Options::default();
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+
+        match arg.as_str() {
+            "--backup" => options.backup = Some(PathBuf::from(value()?)),
+            "--guild" => options.guild = Some(parse_guild(&value()?)?),
+            "--proc" => options.proc = Some(value()?.parse()?),
+            "--out-of" => options.out_of = Some(value()?.parse()?),
+            "--mutators" => {
+                options.mutators = Some(
+                    value()?
+                        .split(',')
+                        .filter(|m| !m.is_empty())
+                        .map(parse_mutator)
+                        .collect::<Result<_, _>>()?,
+                )
+            }
+            "--emojis" => {
+                options.emojis = value()?
+                    .split(',')
+                    .map(|e| ReactionType::Unicode(e.to_string()))
+                    .collect()
+            }
+            "--no-learn" => options.no_learn = true,
+            "--seed" => options.seed = Some(value()?.parse()?),
+            "--replay" => options.replay = Some(value()?.parse()?),
+            "--trigger" => options.trigger = value()?,
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg).into()),
+            _ => options.log = Some(PathBuf::from(arg)),
+        }
+    }
+
+    Ok(options)
+}
+
+fn parse_log(path: &Path, raw: &str) -> Result<Vec<LogEntry>, Error> {
+    if path.extension().is_some_and(|e| e == "json") {
+        return Ok(serde_json::from_str(raw)?);
+    }
+
+    Ok(raw
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|line| {
+            let (author, content) = line.split_once(':').unwrap_or(("user", line));
+
+            LogEntry {
+                author: author.trim().to_string(),
+                content: content.trim().to_string(),
+                mention: content.contains("@bot"),
+            }
+        })
+        .collect())
+}
+
+// Authors that are already ids keep them, everyone else gets a made up one
+fn author_id(authors: &mut HashMap<String, UserId>, author: &str) -> UserId {
+    if let Ok(id) = author.parse::<u64>() {
+        if id > 1 {
+            return UserId::new(id);
+        }
+    }
+
+    let next = UserId::new(1000 + authors.len() as u64);
+    *authors.entry(author.to_string()).or_insert(next)
+}
+
+fn print_events(events: &[TransportEvent]) {
+    for event in events {
+        match event {
+            TransportEvent::Typing { .. } => {}
+            TransportEvent::Sent { content, .. } => println!("< SENT: {}", content),
+            TransportEvent::Replied { reply_to, content, .. } => println!("< REPLIED TO {}: {}", reply_to, content),
+            TransportEvent::Reacted { reaction, .. } => println!("< REACTED: {}", reaction),
+        }
+    }
+}
+
+// Returns what the bot did after each message
+async fn simulate(options: Options) -> Result<Vec<Vec<TransportEvent>>, Error> {
+    let storage = Arc::new(LocalDirStorage { dir: PathBuf::new() });
+    let mut instance = SclunerInstance::new(storage, Vec::new());
+    if let Some(seed) = options.seed {
+        instance.set_seed(seed);
+    }
+
+    if let Some(path) = &options.backup {
+        instance.load_backup(SclunerBackup::from_bytes(&std::fs::read(path)?)?);
+    }
+
+    // Default to the guild with the most memories
+    let guild_id = options.guild.unwrap_or_else(|| {
+        instance
+            .guilds
+            .values()
+            .max_by_key(|g| g.messages.len())
+            .map_or(DEFAULT_GUILD, |g| g.guild_id)
+    });
+
+    let guild = instance.guild_mut(guild_id);
+
+    // Same rules as the proc command
+    let proc = options.proc.unwrap_or(guild.max_proc);
+    let out_of = options.out_of.unwrap_or(guild.proc_out_of);
+    if out_of == 0 || proc > out_of {
+        return Err("proc must be <= out_of, out_of can't be 0".into());
+    }
+
+    guild.asleep = false;
+    guild.nap = None;
+    guild.quiet_hours.clear();
+    if let Some(proc) = options.proc {
+        guild.min_proc = proc;
+        guild.max_proc = proc;
+        guild.proc = proc;
+    }
+    if let Some(out_of) = options.out_of {
+        guild.proc_out_of = out_of;
+    }
+    if let Some(mutators) = options.mutators {
+        guild.allowed_mutators = mutators;
+    }
+
+    let mut transport = MemoryTransport::new(BOT);
+    transport.emojis.insert(guild_id, options.emojis);
+
+    if let Some(seed) = options.replay {
+        println!("REPLAYING REPLY {} IN GUILD {} TO: {}", seed, guild_id, options.trigger);
+        guild.send_seeded(&transport, CHANNEL, &options.trigger, seed).await;
+        let events = transport.take_events();
+        print_events(&events);
+        return Ok(vec![events]);
+    }
+
+    let entries = match &options.log {
+        Some(path) => parse_log(path, &std::fs::read_to_string(path)?)?,
+        None => guild
+            .messages
+            .iter()
+            .map(|m| LogEntry {
+                author: m.user_id.to_string(),
+                content: m.content.clone(),
+                mention: false,
+            })
+            .collect(),
+    };
+
+    println!("SIMULATING {} MESSAGES IN GUILD {} WITH PROC {}/{}", entries.len(), guild_id, guild.proc, guild.proc_out_of);
+
+    let mut authors = HashMap::new();
+    let mut steps = Vec::new();
+    for (i, entry) in entries.into_iter().enumerate() {
+        let mut msg = Message::default();
+        msg.id = MessageId::new(1_000_000 + i as u64);
+        msg.channel_id = CHANNEL;
+        msg.guild_id = Some(guild_id);
+        msg.author.id = author_id(&mut authors, &entry.author);
+        msg.author.name = entry.author.clone();
+        msg.content = entry.content;
+
+        if entry.mention {
+            let mut bot = User::default();
+            bot.id = BOT;
+            msg.mentions.push(bot);
+        }
+
+        if !options.no_learn && !instance.whitelist.contains(&msg.author.id) {
+            instance.whitelist.push(msg.author.id);
+        }
+
+        println!("> {}: {}", entry.author, msg.content);
+        transport.observe(CHANNEL, msg.id);
+        instance.handle_message(&transport, &msg).await;
+        let events = transport.take_events();
+        print_events(&events);
+        steps.push(events);
+    }
+
+    Ok(steps)
+}
+
+fn main() {
+    let options = match parse_options(std::env::args().skip(1)) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(2);
+        }
+    };
+
+    if let Err(e) = async_std::task::block_on(simulate(options)) {
+        eprintln!("SIMULATION FAILED: {}", e);
+        exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_text_logs() {
+        let log = "alice: hello there\n\nbob: hey @bot what's up\njust words";
+
+        assert_eq!(parse_log(Path::new("chat.txt"), log).unwrap(), vec![
+            LogEntry { author: "alice".to_string(), content: "hello there".to_string(), mention: false },
+            LogEntry { author: "bob".to_string(), content: "hey @bot what's up".to_string(), mention: true },
+            LogEntry { author: "user".to_string(), content: "just words".to_string(), mention: false },
+        ]);
+    }
+
+    #[test]
+    fn parses_json_logs() {
+        let log = r#"[{"author": "alice", "content": "hi"}, {"author": "bob", "content": "yo", "mention": true}]"#;
+
+        assert_eq!(parse_log(Path::new("chat.json"), log).unwrap(), vec![
+            LogEntry { author: "alice".to_string(), content: "hi".to_string(), mention: false },
+            LogEntry { author: "bob".to_string(), content: "yo".to_string(), mention: true },
+        ]);
+    }
+
+    #[test]
+    fn replays_a_log() {
+        let dir = std::env::temp_dir().join(format!("scluner_simulate_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("chat.txt");
+        std::fs::write(&log, "alice: the cat sat\nbob: @bot say something").unwrap();
+
+        let options = parse_options(["--proc", "0", "--mutators", "", log.to_str().unwrap()].map(str::to_string).into_iter()).unwrap();
+        let steps = async_std::task::block_on(simulate(options)).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // Nothing to say yet, then the only memory gets replayed when pinged
+        assert!(steps[0].is_empty());
+        assert!(steps[1].iter().any(|e| matches!(e, TransportEvent::Sent { content, .. } if content == "the cat sat")));
+    }
+
+    #[test]
+    fn seeded_runs_repeat() {
+        let dir = std::env::temp_dir().join(format!("scluner_simulate_seed_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("chat.txt");
+        std::fs::write(&log, "alice: he sat on the mat\nbob: she ate his hat\ncarol: it was them\ndave: @bot go on\n".repeat(5)).unwrap();
+
+        let run = || {
+            let args = ["--seed", "9", "--proc", "1", "--out-of", "2", "--emojis", "🐸", log.to_str().unwrap()];
+            async_std::task::block_on(simulate(parse_options(args.map(str::to_string).into_iter()).unwrap())).unwrap()
+        };
+        let (first, second) = (run(), run());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn rejects_bad_options() {
+        let run = |args: &[&str]| {
+            let options = parse_options(args.iter().map(|a| a.to_string()))?;
+            async_std::task::block_on(simulate(options))
+        };
+
+        assert!(run(&["--guild", "0"]).is_err());
+        assert!(run(&["--proc", "5", "--out-of", "2"]).is_err());
+        assert!(run(&["--out-of", "0"]).is_err());
+    }
+}
@@ -1,233 +1,1010 @@
-use poise::serenity_prelude::*;
-use crate::scluner_backup::{SclunerBackup, SclunerBackupCompat};
-use crate::{Context, DataContext, Error};
-
-fn fix_say_result<U>(faulty: Result<U>) -> Result<(), Error> {
-    match faulty {
-        Ok(_) => Ok(()),
-        Err(e) => Err(e.into())
-    }
-}
-
-pub fn ctx_prefix<'a>(ctx: &'a Context<'a>) -> &'a DataContext {
-    match ctx {
-        Context::Application(_) => panic!("That shouldn't happen!"),
-        Context::Prefix(c) => c as &DataContext
-    }
-}
-
-// CHECKS
-pub async fn dev_check(ctx: Context<'_>) -> Result<bool, Error> {
-    Ok(ctx_prefix(&ctx).msg.author.id == 407991620164911118)
-}
-
-pub async fn mod_check(ctx: Context<'_>) -> Result<bool, Error> {
-    let data = ctx.data().lock().await;
-    let msg = ctx_prefix(&ctx).msg;
-    Ok(msg.author.id == 407991620164911118 || ( data.modlist.contains(&msg.author.id) && !data.blacklist.contains(&msg.author.id) ))
-}
-
-pub async fn user_check(ctx: Context<'_>) -> Result<bool, Error> {
-    let msg = ctx_prefix(&ctx).msg;
-    Ok(!ctx.data().lock().await.blacklist.contains(&msg.author.id))
-}
-
-/// USER COMMAND
-/// Deletes all memories with the given content
-#[poise::command(prefix_command, guild_only, check="user_check")]
-pub async fn delete_content(ctx: Context<'_>) -> Result<(), Error> {
-    let mut data = ctx.data().lock().await;
-    let msg = ctx_prefix(&ctx).msg;
-    let msg = match &msg.referenced_message {
-        None => return fix_say_result(ctx.channel_id().say(ctx.http(), "PLEASE REPLY TO MESSAGE TO DELETE").await),
-        Some(m) => m
-    };
-
-    msg.delete(ctx.http()).await?;
-    let guild = data.guilds.get_mut(&ctx.guild_id().unwrap()).unwrap();
-
-    guild.delete_message_content(msg.content.clone());
-
-    fix_say_result(ctx.channel_id().say(ctx.http(), "DELETED ALL MESSAGES WITH CONTENT").await)
-}
-
-/// USER COMMAND
-/// Fetches the information of the posted memory
-#[poise::command(prefix_command, guild_only, check="user_check")]
-pub async fn info_content(ctx: Context<'_>) -> Result<(), Error> {
-    let msg = ctx_prefix(&ctx).msg;
-
-    let m = match &msg.referenced_message {
-        None => return fix_say_result(ctx.channel_id().say(ctx.http(), "PLEASE REPLY TO A SCLUNER MESSAGE TO USE AS CONTENT").await),
-        Some(m) => {
-            m
-        }
-    };
-
-    let mut data = ctx.data().lock().await;
-    let guild = data.guilds.get_mut(&ctx.guild_id().unwrap()).unwrap();
-    let mut fetched_info = "MESSAGE ORIGINALLY SENT BY USERS:\n".to_string();
-
-    for fetched in guild.fetch_from_content(m.content.clone()) {
-        fetched_info += format!("<@{}>\n", fetched.user_id).as_str();
-    }
-
-    fix_say_result(ctx.channel_id().say(ctx.http(), fetched_info).await)
-}
-
-/// USER COMMAND
-/// Shows the proc variables
-#[poise::command(prefix_command, guild_only, check="user_check")]
-pub async fn info_proc(ctx: Context<'_>) -> Result<(), Error> {
-    let mut data = ctx.data().lock().await;
-    let guild = data.guilds.get_mut(&ctx.guild_id().unwrap()).unwrap();
-
-    let info = format!("MIN_PROC:{}\nMAX_PROC:{}\nPROC_OUT_OF:{}\nCHANCE OF RANDOM REPLY: [{}..{}] out of {} tries",
-        guild.min_proc,
-        guild.max_proc,
-        guild.proc_out_of,
-        guild.min_proc, guild.max_proc, guild.proc_out_of,
-    );
-
-    fix_say_result(ctx.channel_id().say(ctx.http(), info).await)
-}
-
-/// USER COMMAND
-/// Shows the proc variables
-#[poise::command(prefix_command, guild_only, check="user_check")]
-pub async fn info(ctx: Context<'_>) -> Result<(), Error> {
-    let mut data = ctx.data().lock().await;
-
-    let running_time = data.startup_instant.elapsed().as_secs() / 3600;
-    let backup_time = data.backup_instant.elapsed().as_secs() / 3600;
-    let guilds_len = data.guilds.len();
-
-    let guild = data.guilds.get_mut(&ctx.guild_id().unwrap()).unwrap();
-
-    let info = format!("SCLUNER v{}\nRUNNING FOR: {}h\nTIME SINCE BACKUP: {}h\nON {} GUILDS\nSTORING {} MESSAGES ON CURRENT ONE",
-        env!("CARGO_PKG_VERSION"),
-        running_time,
-        backup_time,
-        guilds_len,
-        guild.messages.len()
-    );
-
-    fix_say_result(ctx.channel_id().say(ctx.http(), info).await)
-}
-
-/// USER COMMAND
-/// Registers or unregisters the user
-#[poise::command(prefix_command, guild_only, check="user_check")]
-pub async fn whitelist(ctx: Context<'_>) -> Result<(), Error> {
-    let mut data = ctx.data().lock().await;
-    let user_id = ctx.author().id;
-
-    if data.whitelist.contains(&user_id) {
-        data.whitelist.retain(|u| *u != user_id);
-        fix_say_result(ctx.channel_id().say(ctx.http(), format!("REMOVED USER <@{}>", user_id)).await)
-    }
-    else {
-        data.whitelist.push(user_id);
-        fix_say_result(ctx.channel_id().say(ctx.http(), format!("ADDED USER <@{}>", user_id)).await)
-    }
-}
-
-/// MODERATOR COMMAND
-/// Deletes all memories by given user
-#[poise::command(prefix_command, guild_only, check="mod_check")]
-pub async fn delete_user(ctx: Context<'_>, user: User) -> Result<(), Error> {
-    ctx.data().lock().await.guilds.get_mut(&ctx.guild_id().unwrap()).unwrap().delete_message_sender(user.id);
-
-    fix_say_result(ctx.channel_id().say(ctx.http(), format!("DELETED ALL MESSAGES SENT BY <@{}>", user.id)).await)
-}
-
-/// MODERATOR COMMAND
-/// Sets the proc variables
-#[poise::command(prefix_command, guild_only, check="mod_check")]
-pub async fn proc(ctx: Context<'_>, min: u32, max: u32, out_of: u32) -> Result<(), Error> {
-    let mut data = ctx.data().lock().await;
-    let guild = data.guilds.get_mut(&ctx.guild_id().unwrap()).unwrap();
-
-    guild.min_proc = min;
-    guild.max_proc = max;
-    guild.proc_out_of = out_of;
-
-    fix_say_result(ctx.channel_id().say(ctx.http(), "SUCCESSFULLY SET PROC VARS").await)
-}
-
-/// MODERATOR COMMAND
-/// mutes or unmutes the bot
-#[poise::command(prefix_command, guild_only, check="mod_check")]
-pub async fn sleep(ctx: Context<'_>) -> Result<(), Error> {
-    let mut data = ctx.data().lock().await;
-    let guild = data.guilds.get_mut(&ctx.guild_id().unwrap()).unwrap();
-
-    guild.asleep = !guild.asleep;
-
-    match guild.asleep {
-        true => fix_say_result(ctx.channel_id().say(ctx.http(), "A mimir").await),
-        false => fix_say_result(ctx.channel_id().say(ctx.http(), "Good morning!").await)
-    }
-}
-
-/// DEV COMMAND
-/// Adds a moderator
-#[poise::command(prefix_command, guild_only, check="dev_check")]
-pub async fn moderator(ctx: Context<'_>, user: User) -> Result<(), Error> {
-    let mut data = ctx.data().lock().await;
-    if data.modlist.contains(&user.id) {
-        data.modlist.retain(|u| *u != user.id);
-        fix_say_result(ctx.channel_id().say(ctx.http(), format!("REMOVED MODERATOR <@{}>", user.id)).await)
-    }
-    else {
-        data.modlist.push(user.id);
-        fix_say_result(ctx.channel_id().say(ctx.http(), format!("ADDED MODERATOR <@{}>", user.id)).await)
-    }
-}
-
-/// DEV COMMAND
-/// Forces a backup
-#[poise::command(prefix_command, guild_only, check="dev_check")]
-pub async fn backup_send(ctx: Context<'_>) -> Result<(), Error> {
-    let data = ctx.data().lock().await;
-    data.save_backup(ctx.serenity_context()).await;
-    fix_say_result(ctx.channel_id().say(ctx.http(), "SUCCESSFULLY SENT BACKUP TO CHANNEL").await)
-}
-
-/// DEV COMMAND
-/// Loads given backup
-#[poise::command(prefix_command, guild_only, check="dev_check")]
-pub async fn backup_load(ctx: Context<'_>, file: Attachment) -> Result<(), Error> {
-    let backup_bytes = match file.download().await {
-        Ok(f) => f,
-        Err(e) => return fix_say_result(ctx.channel_id().say(ctx.http(), format!("FILE COULDN'T BE DOWNLOADED: {}", e)).await)
-    };
-
-
-    let backup = match ciborium::from_reader::<SclunerBackup, &[u8]>(&backup_bytes) {
-        Ok(b) => b,
-        Err(e) => return fix_say_result(ctx.channel_id().say(ctx.http(), format!("FILE COULDN'T BE DESERIALIZED: {}", e)).await)
-    };
-
-    ctx.data().lock().await.load_backup(backup);
-
-    fix_say_result(ctx.channel_id().say(ctx.http(), "SUCCESSFULLY LOADED BACKUP").await)
-}
-
-#[poise::command(prefix_command, guild_only, check="dev_check")]
-pub async fn backup_load_compat(ctx: Context<'_>, file: Attachment) -> Result<(), Error> {
-    let backup_bytes = match file.download().await {
-        Ok(f) => f,
-        Err(e) => return fix_say_result(ctx.channel_id().say(ctx.http(), format!("FILE COULDN'T BE DOWNLOADED: {}", e)).await)
-    };
-
-
-    let backup = match ciborium::from_reader::<SclunerBackupCompat, &[u8]>(&backup_bytes) {
-        Ok(b) => b,
-        Err(e) => return fix_say_result(ctx.channel_id().say(ctx.http(), format!("FILE COULDN'T BE DESERIALIZED: {}", e)).await)
-    };
-
-    ctx.data().lock().await.load_backup(backup.modernise());
-
-    fix_say_result(ctx.channel_id().say(ctx.http(), "SUCCESSFULLY COMPAT LOADED BACKUP").await)
-}
\ No newline at end of file
+use poise::serenity_prelude::*;
+use crate::activity::AdaptiveRate;
+use crate::mutators::{DefinedMutators, PronounSet, RegexMutator, MAX_REGEX_MUTATORS};
+use crate::schedule::{parse_clock, parse_duration, parse_utc_offset, Nap, QuietHours, MAX_NAP_SECS};
+use crate::scluner_backup::SclunerBackup;
+use crate::user_export::SclunerUserExport;
+use crate::{Context, Error, ProcReroll, ReplyMode};
+
+fn fix_say_result<U>(faulty: Result<U>) -> Result<(), Error> {
+    match faulty {
+        Ok(_) => Ok(()),
+        Err(e) => Err(e.into())
+    }
+}
+
+// The message a prefix command was replying to, slash commands can't reply to anything
+pub fn referenced_message<'a>(ctx: &'a Context<'a>) -> Option<&'a Message> {
+    match ctx {
+        Context::Application(_) => None,
+        Context::Prefix(c) => c.msg.referenced_message.as_deref()
+    }
+}
+
+// Interactions come with permissions, prefix commands need the guild's roles to work them out
+async fn member_permissions(ctx: Context<'_>, member: &Member) -> Permissions {
+    if let Some(p) = member.permissions {
+        return p;
+    }
+
+    match member.guild_id.to_partial_guild(ctx.http()).await {
+        Ok(g) => g.member_permissions(member),
+        Err(e) => {
+            eprintln!("FAILED TO FETCH GUILD FOR PERMISSIONS: {}", e);
+            Permissions::empty()
+        }
+    }
+}
+
+// CHECKS
+pub async fn dev_check(ctx: Context<'_>) -> Result<bool, Error> {
+    Ok(ctx.data().lock().await.owners.contains(&ctx.author().id))
+}
+
+pub async fn mod_check(ctx: Context<'_>) -> Result<bool, Error> {
+    let author_id = ctx.author().id;
+
+    let (mod_role, manage_guild_mods) = {
+        let data = ctx.data().lock().await;
+
+        if data.owners.contains(&author_id) {
+            return Ok(true);
+        }
+        if data.is_blacklisted(ctx.guild_id(), author_id) {
+            return Ok(false);
+        }
+
+        let guild = match ctx.guild_id().and_then(|g| data.guilds.get(&g)) {
+            None => return Ok(false),
+            Some(g) => g,
+        };
+
+        if guild.moderators.contains(&author_id) {
+            return Ok(true);
+        }
+
+        (guild.mod_role, guild.manage_guild_mods)
+    };
+
+    if mod_role.is_none() && !manage_guild_mods {
+        return Ok(false);
+    }
+
+    let member = match ctx.author_member().await {
+        None => return Ok(false),
+        Some(m) => m,
+    };
+
+    if mod_role.is_some_and(|r| member.roles.contains(&r)) {
+        return Ok(true);
+    }
+
+    Ok(manage_guild_mods && member_permissions(ctx, &member).await.manage_guild())
+}
+
+pub async fn user_check(ctx: Context<'_>) -> Result<bool, Error> {
+    Ok(!ctx.data().lock().await.is_blacklisted(ctx.guild_id(), ctx.author().id))
+}
+
+async fn delete_content_of(ctx: Context<'_>, msg: &Message) -> Result<(), Error> {
+    // Images and embeds, would match every memory
+    if msg.content.trim().is_empty() {
+        return fix_say_result(ctx.say("MESSAGE HAS NO TEXT").await);
+    }
+
+    let mut data = ctx.data().lock().await;
+
+    msg.delete(ctx.http()).await?;
+    let guild = data.guild_mut(ctx.guild_id().unwrap());
+
+    guild.delete_message_content(msg.content.clone());
+
+    fix_say_result(ctx.say("DELETED ALL MESSAGES WITH CONTENT").await)
+}
+
+async fn info_content_of(ctx: Context<'_>, msg: &Message) -> Result<(), Error> {
+    let mut data = ctx.data().lock().await;
+    let guild = data.guild_mut(ctx.guild_id().unwrap());
+    let mut fetched_info = "MESSAGE ORIGINALLY SENT BY USERS:\n".to_string();
+
+    // Replies that got changed won't match any memory
+    if let Some(sources) = guild.sources_of(msg.id) {
+        for user_id in sources {
+            fetched_info += format!("<@{}>\n", user_id).as_str();
+        }
+
+        return fix_say_result(ctx.say(fetched_info).await);
+    }
+
+    if msg.content.trim().is_empty() {
+        return fix_say_result(ctx.say("MESSAGE HAS NO TEXT").await);
+    }
+
+    for fetched in guild.fetch_from_content(msg.content.clone()) {
+        fetched_info += format!("<@{}>\n", fetched.user_id).as_str();
+    }
+
+    fix_say_result(ctx.say(fetched_info).await)
+}
+
+/// USER COMMAND
+/// Deletes all memories with the content of the replied message
+#[poise::command(prefix_command, guild_only, check="user_check")]
+pub async fn delete_content(ctx: Context<'_>) -> Result<(), Error> {
+    match referenced_message(&ctx) {
+        None => fix_say_result(ctx.say("PLEASE REPLY TO MESSAGE TO DELETE").await),
+        Some(m) => delete_content_of(ctx, m).await
+    }
+}
+
+/// USER COMMAND
+/// Deletes all memories with the content of this message
+#[poise::command(context_menu_command = "Delete memory", guild_only, check="user_check")]
+pub async fn delete_content_menu(ctx: Context<'_>, msg: Message) -> Result<(), Error> {
+    delete_content_of(ctx, &msg).await
+}
+
+/// USER COMMAND
+/// Fetches the information of the replied memory
+#[poise::command(prefix_command, guild_only, check="user_check")]
+pub async fn info_content(ctx: Context<'_>) -> Result<(), Error> {
+    match referenced_message(&ctx) {
+        None => fix_say_result(ctx.say("PLEASE REPLY TO A SCLUNER MESSAGE TO USE AS CONTENT").await),
+        Some(m) => info_content_of(ctx, m).await
+    }
+}
+
+/// USER COMMAND
+/// Fetches the information of this memory
+#[poise::command(context_menu_command = "Memory info", guild_only, check="user_check")]
+pub async fn info_content_menu(ctx: Context<'_>, msg: Message) -> Result<(), Error> {
+    info_content_of(ctx, &msg).await
+}
+
+/// USER COMMAND
+/// Shows the proc variables
+#[poise::command(prefix_command, slash_command, guild_only, check="user_check")]
+pub async fn info_proc(ctx: Context<'_>) -> Result<(), Error> {
+    let mut data = ctx.data().lock().await;
+    let guild = data.guild_mut(ctx.guild_id().unwrap());
+
+    let reroll = match guild.proc_reroll {
+        ProcReroll::AfterReply => "AFTER EVERY REPLY".to_string(),
+        ProcReroll::Every(secs) => format!("EVERY {} MINUTES", secs / 60),
+    };
+
+    let mut info = format!("MIN_PROC:{}\nMAX_PROC:{}\nPROC_OUT_OF:{}\nPROC:{}\nREROLLED: {}\nCHANCE OF RANDOM REPLY: {} out of {} tries",
+        guild.min_proc,
+        guild.max_proc,
+        guild.proc_out_of,
+        guild.proc,
+        reroll,
+        guild.proc, guild.proc_out_of,
+    );
+
+    if let Some(rate) = &guild.adaptive_rate {
+        info = format!("ADAPTIVE: AIMING FOR {} REPLIES PER HOUR, AT MOST ONE PER {} MINUTES\nPROC IS IGNORED",
+            rate.replies_per_hour,
+            rate.cooldown / 60,
+        );
+    }
+
+    fix_say_result(ctx.say(info).await)
+}
+
+/// USER COMMAND
+/// Shows the settings of this or the given channel
+#[poise::command(prefix_command, slash_command, guild_only, check="user_check")]
+pub async fn info_channel(ctx: Context<'_>, channel: Option<GuildChannel>) -> Result<(), Error> {
+    let channel_id = channel.map_or(ctx.channel_id(), |c| c.id);
+    let mut data = ctx.data().lock().await;
+    let guild = data.guild_mut(ctx.guild_id().unwrap());
+
+    let proc = match guild.channels.get(&channel_id).and_then(|c| c.proc) {
+        None => "GUILD'S".to_string(),
+        Some((proc, out_of)) => format!("{} out of {} tries", proc, out_of),
+    };
+
+    let info = format!("CHANNEL <#{}>\nLEARNING: {}\nSPEAKING: {}\nASLEEP: {}\nCHANCE OF RANDOM REPLY: {}",
+        channel_id,
+        guild.can_learn(channel_id),
+        guild.can_speak(channel_id),
+        guild.is_asleep(channel_id, Timestamp::now().unix_timestamp()),
+        proc,
+    );
+
+    fix_say_result(ctx.say(info).await)
+}
+
+/// USER COMMAND
+/// Shows when the bot sleeps in this guild
+#[poise::command(prefix_command, slash_command, guild_only, check="user_check")]
+pub async fn info_sleep(ctx: Context<'_>) -> Result<(), Error> {
+    let mut data = ctx.data().lock().await;
+    let guild = data.guild_mut(ctx.guild_id().unwrap());
+
+    let nap = match guild.nap {
+        None => "NO".to_string(),
+        // Nothing wakes it up until a message comes in
+        Some(n) => format!("UNTIL <t:{}:f>, THEN ON THE NEXT MESSAGE", n.until),
+    };
+
+    let quiet_hours = match guild.quiet_hours.is_empty() {
+        true => "NONE".to_string(),
+        false => guild.quiet_hours
+            .iter()
+            .enumerate()
+            .map(|(i, q)| format!("\n{}. {}", i, q))
+            .collect::<String>(),
+    };
+
+    let info = format!("ASLEEP: {}\nNAPPING: {}\nUTC OFFSET: {:+} MINUTES\nQUIET HOURS: {}",
+        guild.asleep,
+        nap,
+        guild.utc_offset,
+        quiet_hours,
+    );
+
+    fix_say_result(ctx.say(info).await)
+}
+
+/// USER COMMAND
+/// Shows the emojis this guild uses the most
+#[poise::command(prefix_command, slash_command, guild_only, check="user_check")]
+pub async fn info_emojis(ctx: Context<'_>) -> Result<(), Error> {
+    let mut data = ctx.data().lock().await;
+    let guild = data.guild_mut(ctx.guild_id().unwrap());
+
+    let top = guild.reaction_stats.top(10);
+    if top.is_empty() {
+        return fix_say_result(ctx.say("NO EMOJIS USED YET").await);
+    }
+
+    let info = top
+        .iter()
+        .enumerate()
+        // Custom emojis are kept by id, any name shows them
+        .map(|(i, (emoji, count))| match emoji.parse::<u64>() {
+            Ok(id) => format!("{}. <:e:{}> {}\n", i + 1, id, count),
+            Err(_) => format!("{}. {} {}\n", i + 1, emoji, count),
+        })
+        .collect::<String>();
+
+    fix_say_result(ctx.say(info).await)
+}
+
+/// USER COMMAND
+/// Shows the bot's status
+#[poise::command(prefix_command, slash_command, guild_only, check="user_check")]
+pub async fn info(ctx: Context<'_>) -> Result<(), Error> {
+    let mut data = ctx.data().lock().await;
+
+    let running_time = data.startup_instant.elapsed().as_secs() / 3600;
+    let backup_time = data.backup_instant.elapsed().as_secs() / 3600;
+    let guilds_len = data.guilds.len();
+
+    let guild = data.guild_mut(ctx.guild_id().unwrap());
+
+    let info = format!("SCLUNER v{}\nRUNNING FOR: {}h\nTIME SINCE BACKUP: {}h\nON {} GUILDS\nSTORING {} MESSAGES ON CURRENT ONE",
+        env!("CARGO_PKG_VERSION"),
+        running_time,
+        backup_time,
+        guilds_len,
+        guild.messages.len()
+    );
+
+    fix_say_result(ctx.say(info).await)
+}
+
+/// USER COMMAND
+/// Registers or unregisters the user
+#[poise::command(prefix_command, slash_command, guild_only, check="user_check")]
+pub async fn whitelist(ctx: Context<'_>) -> Result<(), Error> {
+    let mut data = ctx.data().lock().await;
+    let user_id = ctx.author().id;
+
+    if data.whitelist.contains(&user_id) {
+        data.whitelist.retain(|u| *u != user_id);
+        fix_say_result(ctx.say(format!("REMOVED USER <@{}>", user_id)).await)
+    }
+    else {
+        data.whitelist.push(user_id);
+        fix_say_result(ctx.say(format!("ADDED USER <@{}>", user_id)).await)
+    }
+}
+
+/// USER COMMAND
+/// DMs you everything stored about you
+// Not behind user_check, blacklisted users can still get their own data
+#[poise::command(prefix_command, slash_command)]
+pub async fn export_data(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.defer().await?;
+
+    let export = SclunerUserExport::new(&*ctx.data().lock().await, ctx.author().id);
+    let json = serde_json::to_vec_pretty(&export)?;
+
+    let dm = CreateMessage::new()
+        .content(format!("HERE IS EVERYTHING STORED ABOUT YOU, {} MESSAGES IN TOTAL", export.message_count()))
+        .add_file(CreateAttachment::bytes(json, format!("scluner_{}.json", export.user_id)));
+
+    match ctx.author().direct_message(ctx.http(), dm).await {
+        Ok(_) => fix_say_result(ctx.say("SENT YOUR DATA IN DMS").await),
+        Err(e) => fix_say_result(ctx.say(format!("COULDN'T DM YOU: {}", e)).await)
+    }
+}
+
+/// MODERATOR COMMAND
+/// Deletes all memories by given user
+#[poise::command(prefix_command, slash_command, guild_only, check="mod_check")]
+pub async fn delete_user(ctx: Context<'_>, user: User) -> Result<(), Error> {
+    ctx.data().lock().await.guild_mut(ctx.guild_id().unwrap()).delete_message_sender(user.id);
+
+    fix_say_result(ctx.say(format!("DELETED ALL MESSAGES SENT BY <@{}>", user.id)).await)
+}
+
+/// MODERATOR COMMAND
+/// Sets the proc variables
+#[poise::command(prefix_command, slash_command, guild_only, check="mod_check")]
+pub async fn proc(ctx: Context<'_>, min: u32, max: u32, out_of: u32) -> Result<(), Error> {
+    if out_of == 0 || min > max || max > out_of {
+        return fix_say_result(ctx.say("PROC VARS MUST BE MIN <= MAX <= OUT_OF, OUT_OF CAN'T BE 0").await);
+    }
+
+    let mut data = ctx.data().lock().await;
+    let guild = data.guild_mut(ctx.guild_id().unwrap());
+
+    guild.min_proc = min;
+    guild.max_proc = max;
+    guild.proc_out_of = out_of;
+    guild.roll_proc(Timestamp::now().unix_timestamp());
+
+    fix_say_result(ctx.say("SUCCESSFULLY SET PROC VARS").await)
+}
+
+/// MODERATOR COMMAND
+/// Rerolls proc every given minutes, or after every reply if not given
+#[poise::command(prefix_command, slash_command, guild_only, check="mod_check")]
+pub async fn proc_reroll(ctx: Context<'_>, minutes: Option<u32>) -> Result<(), Error> {
+    let mut data = ctx.data().lock().await;
+    let guild = data.guild_mut(ctx.guild_id().unwrap());
+
+    match minutes {
+        None | Some(0) => {
+            guild.proc_reroll = ProcReroll::AfterReply;
+            fix_say_result(ctx.say("REROLLING PROC AFTER EVERY REPLY").await)
+        }
+        Some(m) => {
+            guild.proc_reroll = ProcReroll::Every(m as i64 * 60);
+            fix_say_result(ctx.say(format!("REROLLING PROC EVERY {} MINUTES", m)).await)
+        }
+    }
+}
+
+/// MODERATOR COMMAND
+/// Aims for a number of replies per hour instead of using proc, disabled if not given
+#[poise::command(prefix_command, slash_command, guild_only, check="mod_check")]
+pub async fn adaptive_rate(ctx: Context<'_>, replies_per_hour: Option<u32>, cooldown_minutes: Option<u32>) -> Result<(), Error> {
+    let mut data = ctx.data().lock().await;
+    let guild = data.guild_mut(ctx.guild_id().unwrap());
+
+    match replies_per_hour {
+        None | Some(0) => {
+            guild.adaptive_rate = None;
+            fix_say_result(ctx.say("USING PROC FOR REPLIES").await)
+        }
+        Some(r) => {
+            let cooldown_minutes = cooldown_minutes.unwrap_or(5);
+            guild.adaptive_rate = Some(AdaptiveRate {
+                replies_per_hour: r,
+                cooldown: cooldown_minutes as i64 * 60,
+            });
+            fix_say_result(ctx.say(format!("AIMING FOR {} REPLIES PER HOUR, AT MOST ONE PER {} MINUTES", r, cooldown_minutes)).await)
+        }
+    }
+}
+
+/// MODERATOR COMMAND
+/// mutes or unmutes the bot
+#[poise::command(prefix_command, slash_command, guild_only, check="mod_check")]
+pub async fn sleep(ctx: Context<'_>) -> Result<(), Error> {
+    let mut data = ctx.data().lock().await;
+    let guild = data.guild_mut(ctx.guild_id().unwrap());
+
+    match guild.toggle_sleep(Timestamp::now().unix_timestamp()) {
+        true => fix_say_result(ctx.say("A mimir").await),
+        false => fix_say_result(ctx.say("Good morning!").await)
+    }
+}
+
+/// MODERATOR COMMAND
+/// Naps for a while (e.g. 2h, 1h30m), wakes up here on the next message after
+#[poise::command(prefix_command, slash_command, guild_only, check="mod_check")]
+pub async fn nap(ctx: Context<'_>, duration: String) -> Result<(), Error> {
+    let until = match parse_duration(&duration) {
+        None => return fix_say_result(ctx.say("DURATION SHOULD LOOK LIKE 2h, 45m OR 1h30m").await),
+        Some(s) if s > MAX_NAP_SECS => return fix_say_result(ctx.say("NAPS CAN'T BE LONGER THAN 4 WEEKS").await),
+        Some(s) => match Timestamp::now().unix_timestamp().checked_add(s) {
+            None => return fix_say_result(ctx.say("DURATION IS TOO LONG").await),
+            Some(u) => u,
+        },
+    };
+
+    let mut data = ctx.data().lock().await;
+    let guild = data.guild_mut(ctx.guild_id().unwrap());
+
+    guild.nap = Some(Nap { until, channel_id: ctx.channel_id() });
+
+    fix_say_result(ctx.say(format!("A mimir until <t:{}:t>", until)).await)
+}
+
+/// MODERATOR COMMAND
+/// Sets the guild's UTC offset (e.g. +2, -5:30) used by quiet hours
+#[poise::command(prefix_command, slash_command, guild_only, check="mod_check")]
+pub async fn timezone(ctx: Context<'_>, offset: String) -> Result<(), Error> {
+    let offset = match parse_utc_offset(&offset) {
+        None => return fix_say_result(ctx.say("OFFSET SHOULD LOOK LIKE +2, -5 OR +5:30").await),
+        Some(o) => o,
+    };
+
+    let mut data = ctx.data().lock().await;
+    data.guild_mut(ctx.guild_id().unwrap()).utc_offset = offset;
+
+    fix_say_result(ctx.say(format!("UTC OFFSET IS NOW {:+} MINUTES", offset)).await)
+}
+
+/// MODERATOR COMMAND
+/// Adds a daily window (e.g. 23:00 to 7:00, guild time) where the bot sleeps
+#[poise::command(prefix_command, slash_command, guild_only, check="mod_check")]
+pub async fn quiet_hours_add(ctx: Context<'_>, start: String, end: String) -> Result<(), Error> {
+    let quiet_hours = match (parse_clock(&start), parse_clock(&end)) {
+        (Some(start), Some(end)) if start != end => QuietHours { start, end },
+        _ => return fix_say_result(ctx.say("TIMES SHOULD LOOK LIKE 23:00 OR 7 AND NOT BE THE SAME").await),
+    };
+
+    let mut data = ctx.data().lock().await;
+    data.guild_mut(ctx.guild_id().unwrap()).quiet_hours.push(quiet_hours);
+
+    fix_say_result(ctx.say(format!("ADDED QUIET HOURS {}", quiet_hours)).await)
+}
+
+/// MODERATOR COMMAND
+/// Removes quiet hours by their number in info_sleep
+#[poise::command(prefix_command, slash_command, guild_only, check="mod_check")]
+pub async fn quiet_hours_remove(ctx: Context<'_>, index: usize) -> Result<(), Error> {
+    let mut data = ctx.data().lock().await;
+    let guild = data.guild_mut(ctx.guild_id().unwrap());
+
+    if index >= guild.quiet_hours.len() {
+        return fix_say_result(ctx.say("NO QUIET HOURS WITH THAT NUMBER").await);
+    }
+
+    let removed = guild.quiet_hours.remove(index);
+
+    fix_say_result(ctx.say(format!("REMOVED QUIET HOURS {}", removed)).await)
+}
+
+/// MODERATOR COMMAND
+/// Sets how replies are made up
+#[poise::command(prefix_command, slash_command, guild_only, check="mod_check")]
+pub async fn reply_mode(ctx: Context<'_>, mode: ReplyMode) -> Result<(), Error> {
+    let mut data = ctx.data().lock().await;
+    let guild = data.guild_mut(ctx.guild_id().unwrap());
+
+    guild.reply_mode = mode;
+
+    match mode {
+        ReplyMode::Replay => fix_say_result(ctx.say("REPLAYING MEMORIES").await),
+        ReplyMode::Contextual => fix_say_result(ctx.say("REPLAYING MEMORIES RELATED TO THE CONVERSATION").await),
+        ReplyMode::Markov => fix_say_result(ctx.say("GENERATING FROM MEMORIES").await)
+    }
+}
+
+/// MODERATOR COMMAND
+/// Lists every mutator and whether it's enabled here
+#[poise::command(prefix_command, slash_command, guild_only, check="mod_check")]
+pub async fn mutator_list(ctx: Context<'_>) -> Result<(), Error> {
+    let mut data = ctx.data().lock().await;
+    let guild = data.guild_mut(ctx.guild_id().unwrap());
+
+    let list = DefinedMutators::all()
+        .iter()
+        .map(|m| {
+            let (chance, out_of) = guild.mutator_chance(*m);
+            let enabled = match guild.allowed_mutators.contains(m) {
+                true => "ENABLED",
+                false => "DISABLED",
+            };
+
+            format!("\n{:?}: {}, {} OUT OF {}", m, enabled, chance, out_of)
+        })
+        .collect::<String>();
+
+    let order = match &guild.mutator_order {
+        None => "RANDOM".to_string(),
+        Some(order) => order.iter().map(|m| format!("{:?}", m)).collect::<Vec<_>>().join(", "),
+    };
+    let max = match guild.max_mutators {
+        None => "ALL".to_string(),
+        Some(max) => max.to_string(),
+    };
+
+    let regex_list = guild.regex_mutators
+        .iter()
+        .map(|r| format!("\n{}: `{}` -> `{}`, {} OUT OF {}", r.name, r.pattern, r.replacement, r.chance, r.out_of))
+        .collect::<String>();
+
+    fix_say_result(ctx.say(format!("MUTATORS:{}\nREGEX MUTATORS:{}\nORDER: {}\nMAX PER MESSAGE: {}", list, regex_list, order, max)).await)
+}
+
+/// MODERATOR COMMAND
+/// Lets a mutator change the bot's messages here
+#[poise::command(prefix_command, slash_command, guild_only, check="mod_check")]
+pub async fn mutator_enable(ctx: Context<'_>, mutator: DefinedMutators) -> Result<(), Error> {
+    let mut data = ctx.data().lock().await;
+    let guild = data.guild_mut(ctx.guild_id().unwrap());
+
+    if guild.allowed_mutators.contains(&mutator) {
+        return fix_say_result(ctx.say(format!("{:?} IS ALREADY ENABLED", mutator)).await);
+    }
+
+    guild.allowed_mutators.push(mutator);
+
+    fix_say_result(ctx.say(format!("ENABLED {:?}", mutator)).await)
+}
+
+/// MODERATOR COMMAND
+/// Stops a mutator from changing the bot's messages here
+#[poise::command(prefix_command, slash_command, guild_only, check="mod_check")]
+pub async fn mutator_disable(ctx: Context<'_>, mutator: DefinedMutators) -> Result<(), Error> {
+    let mut data = ctx.data().lock().await;
+    let guild = data.guild_mut(ctx.guild_id().unwrap());
+
+    if !guild.allowed_mutators.contains(&mutator) {
+        return fix_say_result(ctx.say(format!("{:?} IS ALREADY DISABLED", mutator)).await);
+    }
+
+    guild.allowed_mutators.retain(|m| *m != mutator);
+
+    fix_say_result(ctx.say(format!("DISABLED {:?}", mutator)).await)
+}
+
+/// MODERATOR COMMAND
+/// Sets how likely a mutator is to change a message
+#[poise::command(prefix_command, slash_command, guild_only, check="mod_check")]
+pub async fn mutator_chance(ctx: Context<'_>, mutator: DefinedMutators, chance: u32, out_of: u32) -> Result<(), Error> {
+    if out_of == 0 || chance > out_of {
+        return fix_say_result(ctx.say("CHANCE MUST BE <= OUT_OF, OUT_OF CAN'T BE 0").await);
+    }
+
+    let mut data = ctx.data().lock().await;
+    let guild = data.guild_mut(ctx.guild_id().unwrap());
+
+    guild.mutator_chances.insert(mutator, (chance, out_of));
+
+    fix_say_result(ctx.say(format!("{:?} NOW RUNS {} OUT OF {} TIMES", mutator, chance, out_of)).await)
+}
+
+/// MODERATOR COMMAND
+/// Runs mutators in a fixed order, random if not given
+#[poise::command(prefix_command, slash_command, guild_only, check="mod_check")]
+pub async fn mutator_order(ctx: Context<'_>, order: Option<String>) -> Result<(), Error> {
+    let order = match order {
+        None => None,
+        Some(order) => {
+            let parsed = order
+                .split(',')
+                .map(|name| DefinedMutators::parse(name).ok_or(name))
+                .collect::<Result<Vec<_>, _>>();
+
+            match parsed {
+                Err(name) => return fix_say_result(ctx.say(format!("UNKNOWN MUTATOR {}", name.trim())).await),
+                Ok(parsed) => Some(parsed),
+            }
+        }
+    };
+
+    let mut data = ctx.data().lock().await;
+    let guild = data.guild_mut(ctx.guild_id().unwrap());
+
+    guild.mutator_order = order.clone();
+
+    match order {
+        None => fix_say_result(ctx.say("MUTATORS NOW RUN IN A RANDOM ORDER").await),
+        Some(order) => fix_say_result(ctx.say(format!("MUTATORS NOW RUN IN ORDER: {:?}", order)).await),
+    }
+}
+
+/// MODERATOR COMMAND
+/// Whether standard emojis get reacted with and appended next to the server's own
+#[poise::command(prefix_command, slash_command, guild_only, check="mod_check")]
+pub async fn unicode_emojis(ctx: Context<'_>, enabled: bool) -> Result<(), Error> {
+    let mut data = ctx.data().lock().await;
+    data.guild_mut(ctx.guild_id().unwrap()).unicode_emojis = enabled;
+
+    match enabled {
+        true => fix_say_result(ctx.say("STANDARD EMOJIS WILL BE USED TOO").await),
+        false => fix_say_result(ctx.say("ONLY THIS SERVER'S EMOJIS WILL BE USED").await),
+    }
+}
+
+/// MODERATOR COMMAND
+/// Sets how many mutators can change a single message, no limit if not given
+#[poise::command(prefix_command, slash_command, guild_only, check="mod_check")]
+pub async fn max_mutators(ctx: Context<'_>, max: Option<usize>) -> Result<(), Error> {
+    let mut data = ctx.data().lock().await;
+    let guild = data.guild_mut(ctx.guild_id().unwrap());
+
+    guild.max_mutators = max;
+
+    match max {
+        None => fix_say_result(ctx.say("ANY NUMBER OF MUTATORS CAN CHANGE A MESSAGE").await),
+        Some(max) => fix_say_result(ctx.say(format!("UP TO {} MUTATORS CAN CHANGE A MESSAGE", max)).await),
+    }
+}
+
+/// MODERATOR COMMAND
+/// Adds a mutator replacing regex matches, $1 in the replacement inserts a group
+#[poise::command(prefix_command, slash_command, guild_only, check="mod_check")]
+pub async fn regex_mutator_add(
+    ctx: Context<'_>,
+    name: String,
+    pattern: String,
+    replacement: String,
+    chance: u32,
+    out_of: u32,
+) -> Result<(), Error> {
+    let mut data = ctx.data().lock().await;
+    let guild = data.guild_mut(ctx.guild_id().unwrap());
+
+    if guild.regex_mutators.len() >= MAX_REGEX_MUTATORS {
+        return fix_say_result(ctx.say(format!("CAN'T HAVE MORE THAN {} REGEX MUTATORS", MAX_REGEX_MUTATORS)).await);
+    }
+    if guild.regex_mutators.iter().any(|r| r.name == name) {
+        return fix_say_result(ctx.say(format!("A REGEX MUTATOR CALLED {} ALREADY EXISTS", name)).await);
+    }
+
+    let mutator = match RegexMutator::new(name, pattern, replacement, chance, out_of) {
+        Ok(m) => m,
+        Err(e) => return fix_say_result(ctx.say(format!("INVALID REGEX MUTATOR: {}", e)).await),
+    };
+
+    let reply = format!("ADDED REGEX MUTATOR {}", mutator.name);
+    guild.regex_mutators.push(mutator);
+
+    fix_say_result(ctx.say(reply).await)
+}
+
+/// MODERATOR COMMAND
+/// Removes a regex mutator by name
+#[poise::command(prefix_command, slash_command, guild_only, check="mod_check")]
+pub async fn regex_mutator_remove(ctx: Context<'_>, name: String) -> Result<(), Error> {
+    let mut data = ctx.data().lock().await;
+    let guild = data.guild_mut(ctx.guild_id().unwrap());
+
+    let before = guild.regex_mutators.len();
+    guild.regex_mutators.retain(|r| r.name != name);
+
+    match guild.regex_mutators.len() < before {
+        true => fix_say_result(ctx.say(format!("REMOVED REGEX MUTATOR {}", name)).await),
+        false => fix_say_result(ctx.say(format!("NO REGEX MUTATOR CALLED {}", name)).await),
+    }
+}
+
+/// MODERATOR COMMAND
+/// Lists the pronouns Misgendering swaps between
+#[poise::command(prefix_command, slash_command, guild_only, check="mod_check")]
+pub async fn pronoun_set_list(ctx: Context<'_>) -> Result<(), Error> {
+    let mut data = ctx.data().lock().await;
+    let guild = data.guild_mut(ctx.guild_id().unwrap());
+
+    let list = guild.pronoun_sets
+        .iter()
+        .map(|set| format!("\n{}", set))
+        .collect::<String>();
+
+    fix_say_result(ctx.say(format!("PRONOUN SETS:{}", list)).await)
+}
+
+/// MODERATOR COMMAND
+/// Adds pronouns for Misgendering to use, e.g. xe xem xyr
+#[poise::command(prefix_command, slash_command, guild_only, check="mod_check")]
+pub async fn pronoun_set_add(ctx: Context<'_>, primary: String, other: String, owning: String) -> Result<(), Error> {
+    let set = PronounSet::new(&primary, &other, &owning);
+
+    if [&set.primary, &set.other, &set.owning].iter().any(|p| p.is_empty() || !p.chars().all(char::is_alphabetic)) {
+        return fix_say_result(ctx.say("PRONOUNS CAN ONLY HAVE LETTERS").await);
+    }
+
+    let mut data = ctx.data().lock().await;
+    let guild = data.guild_mut(ctx.guild_id().unwrap());
+
+    if guild.pronoun_sets.contains(&set) {
+        return fix_say_result(ctx.say(format!("{} IS ALREADY A PRONOUN SET", set)).await);
+    }
+
+    let reply = format!("ADDED PRONOUN SET {}", set);
+    guild.pronoun_sets.push(set);
+
+    fix_say_result(ctx.say(reply).await)
+}
+
+/// MODERATOR COMMAND
+/// Removes pronouns from Misgendering by their first word, e.g. xe
+#[poise::command(prefix_command, slash_command, guild_only, check="mod_check")]
+pub async fn pronoun_set_remove(ctx: Context<'_>, primary: String) -> Result<(), Error> {
+    let mut data = ctx.data().lock().await;
+    let guild = data.guild_mut(ctx.guild_id().unwrap());
+
+    let primary = primary.to_lowercase();
+    let before = guild.pronoun_sets.len();
+    guild.pronoun_sets.retain(|set| set.primary != primary);
+
+    match guild.pronoun_sets.len() < before {
+        true => fix_say_result(ctx.say(format!("REMOVED PRONOUN SETS STARTING WITH {}", primary)).await),
+        false => fix_say_result(ctx.say(format!("NO PRONOUN SET STARTS WITH {}", primary)).await),
+    }
+}
+
+/// MODERATOR COMMAND
+/// Sets the markov chain variables
+#[poise::command(prefix_command, slash_command, guild_only, check="mod_check")]
+pub async fn markov(ctx: Context<'_>, order: usize, max_len: usize) -> Result<(), Error> {
+    if !(1..=4).contains(&order) {
+        return fix_say_result(ctx.say("ORDER MUST BE BETWEEN 1 AND 4").await);
+    }
+    if !(1..=100).contains(&max_len) {
+        return fix_say_result(ctx.say("MAX LENGTH MUST BE BETWEEN 1 AND 100").await);
+    }
+
+    let mut data = ctx.data().lock().await;
+    let guild = data.guild_mut(ctx.guild_id().unwrap());
+
+    guild.markov_order = order;
+    guild.markov_max_len = max_len;
+
+    fix_say_result(ctx.say("SUCCESSFULLY SET MARKOV VARS").await)
+}
+
+/// MODERATOR COMMAND
+/// Sets or clears the role that makes members moderators
+#[poise::command(prefix_command, slash_command, guild_only, check="mod_check")]
+pub async fn mod_role(ctx: Context<'_>, role: Option<Role>) -> Result<(), Error> {
+    let mut data = ctx.data().lock().await;
+    let guild = data.guild_mut(ctx.guild_id().unwrap());
+
+    guild.mod_role = role.as_ref().map(|r| r.id);
+
+    match role {
+        None => fix_say_result(ctx.say("CLEARED MODERATOR ROLE").await),
+        Some(r) => fix_say_result(ctx.say(format!("SET MODERATOR ROLE TO {}", r.name)).await)
+    }
+}
+
+/// MODERATOR COMMAND
+/// Sets whether members who can manage the server are moderators
+#[poise::command(prefix_command, slash_command, guild_only, check="mod_check")]
+pub async fn mod_permission(ctx: Context<'_>, enabled: bool) -> Result<(), Error> {
+    let mut data = ctx.data().lock().await;
+    let guild = data.guild_mut(ctx.guild_id().unwrap());
+
+    guild.manage_guild_mods = enabled;
+
+    match enabled {
+        true => fix_say_result(ctx.say("SERVER MANAGERS ARE NOW MODERATORS").await),
+        false => fix_say_result(ctx.say("SERVER MANAGERS ARE NO LONGER MODERATORS").await)
+    }
+}
+
+/// MODERATOR COMMAND
+/// Blacklists a user here or globally, optionally purging their memories
+#[poise::command(prefix_command, slash_command, guild_only, check="mod_check")]
+pub async fn blacklist(ctx: Context<'_>, user: User, global: Option<bool>, purge: Option<bool>) -> Result<(), Error> {
+    let global = global.unwrap_or(false);
+    let purge = purge.unwrap_or(false);
+    let mut data = ctx.data().lock().await;
+
+    if data.owners.contains(&user.id) {
+        return fix_say_result(ctx.say("CAN'T BLACKLIST AN OWNER").await);
+    }
+    if global && !data.owners.contains(&ctx.author().id) {
+        return fix_say_result(ctx.say("ONLY OWNERS CAN BLACKLIST GLOBALLY").await);
+    }
+
+    if global {
+        if !data.blacklist.contains(&user.id) {
+            data.blacklist.push(user.id);
+        }
+
+        if purge {
+            for guild in data.guilds.values_mut() {
+                guild.delete_message_sender(user.id);
+            }
+        }
+    }
+    else {
+        let guild = data.guild_mut(ctx.guild_id().unwrap());
+
+        if !guild.blacklist.contains(&user.id) {
+            guild.blacklist.push(user.id);
+        }
+
+        if purge {
+            guild.delete_message_sender(user.id);
+        }
+    }
+
+    let scope = if global { "GLOBALLY" } else { "IN THIS GUILD" };
+    match purge {
+        true => fix_say_result(ctx.say(format!("BLACKLISTED <@{}> {} AND DELETED THEIR MESSAGES", user.id, scope)).await),
+        false => fix_say_result(ctx.say(format!("BLACKLISTED <@{}> {}", user.id, scope)).await)
+    }
+}
+
+/// MODERATOR COMMAND
+/// Removes a user from this guild's blacklist, or the global one if global
+#[poise::command(prefix_command, slash_command, guild_only, check="mod_check")]
+pub async fn unblacklist(ctx: Context<'_>, user: User, global: Option<bool>) -> Result<(), Error> {
+    let global = global.unwrap_or(false);
+    let mut data = ctx.data().lock().await;
+
+    if global && !data.owners.contains(&ctx.author().id) {
+        return fix_say_result(ctx.say("ONLY OWNERS CAN UNBLACKLIST GLOBALLY").await);
+    }
+
+    let list = match global {
+        true => &mut data.blacklist,
+        false => &mut data.guild_mut(ctx.guild_id().unwrap()).blacklist,
+    };
+
+    if !list.contains(&user.id) {
+        return fix_say_result(ctx.say(format!("<@{}> ISN'T BLACKLISTED", user.id)).await);
+    }
+
+    list.retain(|u| *u != user.id);
+
+    fix_say_result(ctx.say(format!("UNBLACKLISTED <@{}>", user.id)).await)
+}
+
+/// MODERATOR COMMAND
+/// Lists the blacklisted users
+#[poise::command(prefix_command, slash_command, guild_only, check="mod_check")]
+pub async fn blacklist_list(ctx: Context<'_>) -> Result<(), Error> {
+    let mut data = ctx.data().lock().await;
+
+    let mut info = "GLOBAL BLACKLIST:\n".to_string();
+    for user_id in &data.blacklist {
+        info += format!("<@{}>\n", user_id).as_str();
+    }
+
+    info += "GUILD BLACKLIST:\n";
+    for user_id in &data.guild_mut(ctx.guild_id().unwrap()).blacklist {
+        info += format!("<@{}>\n", user_id).as_str();
+    }
+
+    fix_say_result(ctx.say(info).await)
+}
+
+/// MODERATOR COMMAND
+/// Sets whether channels without their own settings are learned from and spoken in
+#[poise::command(prefix_command, slash_command, guild_only, check="mod_check")]
+pub async fn channel_defaults(ctx: Context<'_>, learn: bool, speak: bool) -> Result<(), Error> {
+    let mut data = ctx.data().lock().await;
+    let guild = data.guild_mut(ctx.guild_id().unwrap());
+
+    guild.learn_by_default = learn;
+    guild.speak_by_default = speak;
+
+    fix_say_result(ctx.say(format!("CHANNELS NOW DEFAULT TO LEARNING: {}, SPEAKING: {}", learn, speak)).await)
+}
+
+/// MODERATOR COMMAND
+/// Allows or denies learning from a channel, follows the default if not given
+#[poise::command(prefix_command, slash_command, guild_only, check="mod_check")]
+pub async fn channel_learn(ctx: Context<'_>, channel: GuildChannel, allow: Option<bool>) -> Result<(), Error> {
+    let mut data = ctx.data().lock().await;
+    let guild = data.guild_mut(ctx.guild_id().unwrap());
+
+    guild.channels.entry(channel.id).or_default().learn = allow;
+
+    fix_say_result(ctx.say(format!("LEARNING IN <#{}>: {}", channel.id, guild.can_learn(channel.id))).await)
+}
+
+/// MODERATOR COMMAND
+/// Allows or denies speaking in a channel, follows the default if not given
+#[poise::command(prefix_command, slash_command, guild_only, check="mod_check")]
+pub async fn channel_speak(ctx: Context<'_>, channel: GuildChannel, allow: Option<bool>) -> Result<(), Error> {
+    let mut data = ctx.data().lock().await;
+    let guild = data.guild_mut(ctx.guild_id().unwrap());
+
+    guild.channels.entry(channel.id).or_default().speak = allow;
+
+    fix_say_result(ctx.say(format!("SPEAKING IN <#{}>: {}", channel.id, guild.can_speak(channel.id))).await)
+}
+
+/// MODERATOR COMMAND
+/// Sets a fixed reply chance for a channel, uses the guild's if not given
+#[poise::command(prefix_command, slash_command, guild_only, check="mod_check")]
+pub async fn channel_proc(ctx: Context<'_>, channel: GuildChannel, proc: Option<u32>, out_of: Option<u32>) -> Result<(), Error> {
+    let mut data = ctx.data().lock().await;
+    let guild = data.guild_mut(ctx.guild_id().unwrap());
+
+    match (proc, out_of) {
+        (Some(proc), Some(out_of)) => {
+            if out_of == 0 || proc > out_of {
+                return fix_say_result(ctx.say("PROC MUST BE <= OUT_OF, OUT_OF CAN'T BE 0").await);
+            }
+
+            guild.channels.entry(channel.id).or_default().proc = Some((proc, out_of));
+            fix_say_result(ctx.say(format!("SET PROC VARS FOR <#{}>", channel.id)).await)
+        }
+        (None, None) => {
+            guild.channels.entry(channel.id).or_default().proc = None;
+            fix_say_result(ctx.say(format!("<#{}> NOW USES THE GUILD'S PROC VARS", channel.id)).await)
+        }
+        _ => fix_say_result(ctx.say("GIVE BOTH PROC AND OUT_OF, OR NEITHER").await)
+    }
+}
+
+/// MODERATOR COMMAND
+/// mutes or unmutes the bot in a channel
+#[poise::command(prefix_command, slash_command, guild_only, check="mod_check")]
+pub async fn channel_sleep(ctx: Context<'_>, channel: GuildChannel) -> Result<(), Error> {
+    let mut data = ctx.data().lock().await;
+    let guild = data.guild_mut(ctx.guild_id().unwrap());
+    let settings = guild.channels.entry(channel.id).or_default();
+
+    settings.asleep = !settings.asleep;
+
+    match settings.asleep {
+        true => fix_say_result(ctx.say(format!("A mimir in <#{}>", channel.id)).await),
+        false => fix_say_result(ctx.say(format!("Good morning <#{}>!", channel.id)).await)
+    }
+}
+
+/// DEV COMMAND
+/// Adds or removes a moderator of this guild
+#[poise::command(prefix_command, slash_command, guild_only, check="dev_check")]
+pub async fn moderator(ctx: Context<'_>, user: User) -> Result<(), Error> {
+    let mut data = ctx.data().lock().await;
+    let guild = data.guild_mut(ctx.guild_id().unwrap());
+
+    if guild.moderators.contains(&user.id) {
+        guild.moderators.retain(|u| *u != user.id);
+        fix_say_result(ctx.say(format!("REMOVED MODERATOR <@{}>", user.id)).await)
+    }
+    else {
+        guild.moderators.push(user.id);
+        fix_say_result(ctx.say(format!("ADDED MODERATOR <@{}>", user.id)).await)
+    }
+}
+
+/// DEV COMMAND
+/// Forces a backup
+#[poise::command(prefix_command, slash_command, guild_only, check="dev_check")]
+pub async fn backup_send(ctx: Context<'_>) -> Result<(), Error> {
+    ctx.defer().await?;
+    let data = ctx.data().lock().await;
+    data.save_backup().await;
+    fix_say_result(ctx.say("SUCCESSFULLY SAVED BACKUP").await)
+}
+
+/// DEV COMMAND
+/// Loads given backup
+#[poise::command(prefix_command, slash_command, guild_only, check="dev_check")]
+pub async fn backup_load(ctx: Context<'_>, file: Attachment) -> Result<(), Error> {
+    ctx.defer().await?;
+    let backup_bytes = match file.download().await {
+        Ok(f) => f,
+        Err(e) => return fix_say_result(ctx.say(format!("FILE COULDN'T BE DOWNLOADED: {}", e)).await)
+    };
+
+
+    let backup = match SclunerBackup::from_bytes(&backup_bytes) {
+        Ok(b) => b,
+        Err(e) => return fix_say_result(ctx.say(format!("FILE COULDN'T BE LOADED: {}", e)).await)
+    };
+
+    ctx.data().lock().await.load_backup(backup);
+
+    fix_say_result(ctx.say("SUCCESSFULLY LOADED BACKUP").await)
+}
@@ -0,0 +1,37 @@
+use poise::serenity_prelude::*;
+
+// Refetch in case an emoji update event got missed
+pub const EMOJI_TTL_SECS: i64 = 3600;
+
+// Used alongside the guild's own when it allows them
+pub const UNICODE_EMOJIS: [&str; 32] = [
+    "😂", "🤣", "😭", "😳", "🥺", "😎", "🤔", "🙄", "😴", "🤡", "💀", "👀",
+    "👍", "👎", "👏", "🙏", "💯", "🔥", "✨", "❤️", "💔", "🎉", "🍞", "🐸",
+    "🐛", "🦆", "🐟", "🍕", "🥴", "😈", "🗿", "🤝",
+];
+
+// A guild's custom emojis as of fetched_at
+#[derive(Clone)]
+pub struct EmojiCache {
+    // Unix timestamp
+    fetched_at: i64,
+    emojis: Vec<ReactionType>,
+}
+
+impl EmojiCache {
+    pub fn new(emojis: Vec<ReactionType>, now: i64) -> Self {
+        Self {
+            fetched_at: now,
+            emojis,
+        }
+    }
+
+    pub fn is_stale(&self, now: i64) -> bool {
+        now - self.fetched_at >= EMOJI_TTL_SECS
+    }
+
+    pub fn emojis(&self) -> &[ReactionType] {
+        &self.emojis
+    }
+}
+
@@ -0,0 +1,1382 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_std::sync::Mutex;
+use poise::serenity_prelude as serenity;
+use poise::serenity_prelude::*;
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use serde::{Deserialize, Serialize};
+
+use crate::activity::{AdaptiveRate, ChannelActivity};
+use crate::backup_storage::BackupStorageRef;
+use crate::commands::*;
+use crate::emoji_cache::{EmojiCache, UNICODE_EMOJIS};
+use crate::reaction_stats::ReactionStats;
+use crate::markov::MarkovChain;
+use crate::mutators::{DefinedMutators, MutatorRef, PronounSet, RegexMutator};
+use crate::schedule::{Nap, QuietHours};
+use crate::scluner_backup::SclunerBackup;
+use crate::transport::{SerenityTransport, Transport};
+use crate::vocabulary::Vocabulary;
+
+pub mod activity;
+pub mod backup_storage;
+mod commands;
+mod emoji_cache;
+mod reaction_stats;
+mod markov;
+pub mod memory_transport;
+mod retrieval;
+pub mod schedule;
+pub mod transport;
+mod vocabulary;
+mod user_export;
+pub mod scluner_backup;
+pub mod mutators;
+
+pub type Error = Box<dyn std::error::Error + Send + Sync>;
+pub type SclunerRng = StdRng;
+
+// How many sent replies keep track of whose memories they came from
+const MAX_REPLY_SOURCES: usize = 500;
+pub type SclunerRef = Arc<Mutex<SclunerInstance>>;
+type Context<'a> = poise::Context<'a, SclunerRef, Error>;
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SclunerMessage {
+    pub user_id: UserId,
+    pub content: String,
+}
+
+impl SclunerMessage {
+    pub fn new(msg: &Message) -> Self {
+        Self {
+            user_id: msg.author.id,
+            content: msg.content.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, poise::ChoiceParameter)]
+pub enum ReplyMode {
+    Replay,
+    Contextual,
+    Markov,
+}
+
+// When proc gets rolled again from [min_proc, max_proc]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum ProcReroll {
+    AfterReply,
+    // Seconds
+    Every(i64),
+}
+
+// Per channel overrides of the guild's settings
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct SclunerChannel {
+    // None follows the guild's default
+    pub learn: Option<bool>,
+    pub speak: Option<bool>,
+    pub asleep: bool,
+    // Fixed (proc, proc_out_of) chance used instead of the guild's
+    pub proc: Option<(u32, u32)>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct SclunerGuild {
+    pub guild_id: GuildId,
+    pub messages: Vec<SclunerMessage>,
+    pub asleep: bool,
+
+    // Minutes ahead of UTC, quiet hours are in this time
+    pub utc_offset: i32,
+    pub quiet_hours: Vec<QuietHours>,
+    pub nap: Option<Nap>,
+
+    pub channels: HashMap<ChannelId, SclunerChannel>,
+    // Whether channels without an override can be learned from and spoken in
+    pub learn_by_default: bool,
+    pub speak_by_default: bool,
+
+    pub allowed_mutators: Vec<DefinedMutators>,
+    // (chance, out_of) per mutator, missing ones use their default
+    pub mutator_chances: HashMap<DefinedMutators, (u32, u32)>,
+    // Mutators run in this order when set, shuffled otherwise
+    pub mutator_order: Option<Vec<DefinedMutators>>,
+    // Most mutators that can change a single message
+    pub max_mutators: Option<usize>,
+    // Run after the defined mutators
+    pub regex_mutators: Vec<RegexMutator>,
+    // Swapped between by Misgendering
+    pub pronoun_sets: Vec<PronounSet>,
+    // Authors of the memories each recent reply was made from
+    pub reply_sources: VecDeque<(MessageId, Vec<UserId>)>,
+    // Whether standard emojis get used next to the guild's own
+    pub unicode_emojis: bool,
+    // Which emojis members use and on what, so reactions can follow suit
+    pub reaction_stats: ReactionStats,
+
+    pub min_proc: u32,
+    pub max_proc: u32,
+    pub proc_out_of: u32,
+
+    pub proc: u32,
+    pub proc_reroll: ProcReroll,
+    // Unix timestamp
+    pub proc_rolled_at: i64,
+    // Overrides proc when set
+    pub adaptive_rate: Option<AdaptiveRate>,
+
+    pub moderators: Vec<UserId>,
+    // Members with this role count as moderators
+    pub mod_role: Option<RoleId>,
+    // Members with the Manage Server permission count as moderators
+    pub manage_guild_mods: bool,
+
+    // Only applies to this guild, see SclunerInstance::blacklist for everywhere
+    pub blacklist: Vec<UserId>,
+
+    pub reply_mode: ReplyMode,
+    pub markov_order: usize,
+    pub markov_max_len: usize,
+
+    // Built from messages when first needed
+    #[serde(skip)]
+    markov: Option<MarkovChain>,
+    #[serde(skip)]
+    vocabulary: Option<Vocabulary>,
+    #[serde(skip)]
+    emoji_cache: Option<EmojiCache>,
+    #[serde(skip)]
+    activity: HashMap<ChannelId, ChannelActivity>,
+    // Reseeded by the instance when it has a seed
+    #[serde(skip, default = "SclunerRng::from_os_rng")]
+    rng: SclunerRng,
+}
+
+// random_ratio that never panics, out_of 0 never hits and proc past out_of always does
+fn roll_ratio(rng: &mut SclunerRng, proc: u32, out_of: u32) -> bool {
+    out_of > 0 && rng.random_ratio(proc.min(out_of), out_of)
+}
+
+impl SclunerGuild {
+    pub fn new(guild_id: GuildId, mut rng: SclunerRng) -> Self {
+        println!("NEW GUILD REGISTERED: {}", guild_id);
+
+        Self {
+            guild_id,
+            messages: Vec::new(),
+            asleep: false,
+
+            utc_offset: 0,
+            quiet_hours: Vec::new(),
+            nap: None,
+
+            channels: HashMap::new(),
+            learn_by_default: true,
+            speak_by_default: true,
+
+            allowed_mutators: DefinedMutators::default_allowed(),
+            mutator_chances: HashMap::new(),
+            mutator_order: None,
+            max_mutators: None,
+            regex_mutators: Vec::new(),
+            pronoun_sets: PronounSet::default_sets(),
+            reply_sources: VecDeque::new(),
+            unicode_emojis: false,
+            reaction_stats: ReactionStats::default(),
+
+            min_proc: 1,
+            max_proc: 4,
+            proc_out_of: 18,
+
+            proc: rng.random_range(1..=4),
+            proc_reroll: ProcReroll::AfterReply,
+            proc_rolled_at: Timestamp::now().unix_timestamp(),
+            adaptive_rate: None,
+
+            moderators: Vec::new(),
+            mod_role: None,
+            manage_guild_mods: false,
+
+            blacklist: Vec::new(),
+
+            reply_mode: ReplyMode::Replay,
+            markov_order: 2,
+            markov_max_len: 30,
+
+            markov: None,
+            vocabulary: None,
+            emoji_cache: None,
+            activity: HashMap::new(),
+            rng,
+        }
+    }
+
+    pub fn roll_proc(&mut self, now: i64) {
+        // Backups from before the proc command checked anything can have max_proc past proc_out_of
+        let max = self.max_proc.min(self.proc_out_of);
+        self.proc = self.rng.random_range(self.min_proc.min(max)..=max);
+        self.proc_rolled_at = now;
+    }
+
+    fn maybe_reroll_proc(&mut self, now: i64) {
+        if let ProcReroll::Every(secs) = self.proc_reroll {
+            if now - self.proc_rolled_at >= secs {
+                self.roll_proc(now);
+            }
+        }
+    }
+
+    pub fn mutator_chance(&self, mutator: DefinedMutators) -> (u32, u32) {
+        self.mutator_chances
+            .get(&mutator)
+            .copied()
+            .unwrap_or_else(|| mutator.default_chance())
+    }
+
+    // Every mutator that can run in the order they should, with their names and chances
+    fn mutator_pipeline(&self, rng: &mut SclunerRng) -> Vec<(String, (u32, u32), MutatorRef)> {
+        let mut defined = self.allowed_mutators.clone();
+
+        match &self.mutator_order {
+            None => defined.shuffle(rng),
+            // Ones missing from the order go last
+            Some(order) => defined.sort_by_key(|m| order.iter().position(|o| o == m).unwrap_or(order.len())),
+        }
+
+        let mut pipeline: Vec<_> = defined
+            .iter()
+            .zip(DefinedMutators::to_mutators(&defined))
+            .map(|(d, mutator)| (format!("{:?}", d), self.mutator_chance(*d), mutator))
+            .collect();
+
+        for regex in &self.regex_mutators {
+            pipeline.push((regex.name.clone(), (regex.chance, regex.out_of), Arc::new(regex.clone()) as MutatorRef));
+        }
+
+        pipeline
+    }
+
+    pub fn can_learn(&self, channel_id: ChannelId) -> bool {
+        self.channels
+            .get(&channel_id)
+            .and_then(|c| c.learn)
+            .unwrap_or(self.learn_by_default)
+    }
+
+    pub fn can_speak(&self, channel_id: ChannelId) -> bool {
+        self.channels
+            .get(&channel_id)
+            .and_then(|c| c.speak)
+            .unwrap_or(self.speak_by_default)
+    }
+
+    pub fn is_asleep(&self, channel_id: ChannelId, now: i64) -> bool {
+        self.asleep
+            || self.nap.is_some_and(|n| now < n.until)
+            || self.quiet_hours.iter().any(|q| q.contains(self.utc_offset, now))
+            || self.channels.get(&channel_id).is_some_and(|c| c.asleep)
+    }
+
+    // Returns whether it's asleep now, waking up early if it's napping
+    pub fn toggle_sleep(&mut self, now: i64) -> bool {
+        // Naps that already ended only get cleared on the next message
+        match self.nap.take() {
+            Some(n) if now < n.until => self.asleep = false,
+            _ => self.asleep = !self.asleep,
+        }
+
+        self.asleep
+    }
+
+    // Ends a nap that's run out and says so where it started
+    // Only checked on messages, so it wakes up on the first one after the nap
+    async fn maybe_wake(&mut self, transport: &dyn Transport, now: i64) {
+        let nap = match self.nap {
+            Some(n) if now >= n.until => n,
+            _ => return,
+        };
+        self.nap = None;
+
+        println!("GUILD {} WOKE UP FROM A NAP", self.guild_id);
+        // Still not allowed to talk there
+        if self.is_asleep(nap.channel_id, now) || !self.can_speak(nap.channel_id) {
+            return;
+        }
+
+        if let Err(e) = transport.send_message(nap.channel_id, "Good morning!".to_string()).await {
+            eprintln!("COULDN'T ANNOUNCE WAKING UP: {}", e);
+        }
+    }
+
+    // Whether to reply without being pinged
+    fn roll_reply(&mut self, channel_id: ChannelId, now: i64) -> bool {
+        let channel_proc = self.channels.get(&channel_id).and_then(|c| c.proc);
+        let activity = self.activity.entry(channel_id).or_default();
+        activity.record_message(now);
+
+        let procced = match (channel_proc, &self.adaptive_rate) {
+            (Some((proc, out_of)), _) => roll_ratio(&mut self.rng, proc, out_of),
+            (None, None) => roll_ratio(&mut self.rng, self.proc, self.proc_out_of),
+            (None, Some(rate)) => self.rng.random_bool(activity.reply_chance(rate, now)),
+        };
+
+        if procced {
+            activity.record_reply(now);
+        }
+
+        procced
+    }
+
+    fn markov(&mut self) -> &MarkovChain {
+        if self.markov.as_ref().is_none_or(|c| c.order() != self.markov_order) {
+            self.markov = Some(MarkovChain::build(self.markov_order, &self.messages));
+        }
+
+        self.markov.as_ref().unwrap()
+    }
+
+    // The reply and the authors of the memory it came from, generated ones have none
+    fn pick_reply(&mut self, trigger: &str, rng: &mut SclunerRng) -> Option<(String, Vec<UserId>)> {
+        match self.reply_mode {
+            ReplyMode::Replay => self.messages.choose(rng).map(|m| (m.content.clone(), vec![m.user_id])),
+            ReplyMode::Contextual => retrieval::pick_related(trigger, &self.messages, rng).map(|m| (m.content.clone(), vec![m.user_id])),
+            ReplyMode::Markov => {
+                let max_len = self.markov_max_len;
+                self.markov().generate(rng, max_len).map(|m| (m, Vec::new()))
+            }
+        }
+    }
+
+    fn record_sources(&mut self, message_id: MessageId, mut sources: Vec<UserId>) {
+        sources.sort();
+        sources.dedup();
+
+        self.reply_sources.push_back((message_id, sources));
+        if self.reply_sources.len() > MAX_REPLY_SOURCES {
+            self.reply_sources.pop_front();
+        }
+    }
+
+    pub fn sources_of(&self, message_id: MessageId) -> Option<&[UserId]> {
+        self.reply_sources
+            .iter()
+            .find(|(id, _)| *id == message_id)
+            .map(|(_, sources)| sources.as_slice())
+    }
+
+    async fn send_random(&mut self, transport: &dyn Transport, channel_id: ChannelId, trigger: &str) {
+        let seed = self.rng.random();
+        println!("\tReply seed: {}", seed);
+
+        self.send_seeded(transport, channel_id, trigger, seed).await;
+    }
+
+    // Everything in a reply comes from its seed, so logged seeds can be replayed
+    pub async fn send_seeded(&mut self, transport: &dyn Transport, channel_id: ChannelId, trigger: &str, seed: u64) {
+        let mut rng = SclunerRng::seed_from_u64(seed);
+
+        // fake typing
+        let typing = transport.start_typing(channel_id);
+
+        let mut keep_going = true;
+        let mut last_msg: Option<MessageId> = None;
+        while keep_going {
+            keep_going = rng.random_ratio(1, 4);
+
+            let (mut message, mut sources) = match self.pick_reply(trigger, &mut rng) {
+                None => {
+                    eprintln!("FAILED TO SEND RANDOM RESPONSE: NO RECORDED MESSAGES");
+                    return;
+                }
+                Some(m) => m
+            };
+
+            self.prepare_vocabulary();
+            self.refresh_emojis(transport).await;
+            message = SclunerInstance::maybe_mutate(message.clone(), transport, self, &mut rng, &mut sources).await;
+
+            transport.wait(Duration::from_millis(
+                (100 * message.split_whitespace().count()) as u64,
+            ))
+            .await;
+
+            let sent = match last_msg {
+                None => transport.send_message(channel_id, message).await,
+                Some(m) => {
+                    // reply to message if it's left behind
+                    match transport.last_message_id(channel_id).await {
+                        Ok(Some(last)) if last != m => transport.reply(channel_id, m, message).await,
+                        _ => transport.send_message(channel_id, message).await,
+                    }
+                }
+            };
+
+            last_msg = match sent {
+                Ok(m) => {
+                    if !sources.is_empty() {
+                        self.record_sources(m, sources);
+                    }
+                    Some(m)
+                }
+                Err(e) => {
+                    eprintln!("FAILED TO SEND RANDOM RESPONSE: {}", e);
+                    None
+                }
+            };
+        }
+
+        drop(typing);
+    }
+
+    async fn maybe_react_random(&mut self, transport: &dyn Transport, msg: &Message) {
+        self.refresh_emojis(transport).await;
+        let emojis = self.reaction_weights(&msg.content);
+        if emojis.is_empty() {
+            return;
+        }
+
+        // More likely on the kind of message people react to
+        let mut keep_going = match self.reaction_stats.knows_words(&msg.content) {
+            true => self.rng.random_ratio(1, 4),
+            false => self.rng.random_ratio(1, 8),
+        };
+        while keep_going {
+            keep_going = self.rng.random_ratio(1, 4);
+
+            let emote = match emojis.choose_weighted(&mut self.rng, |(_, w)| *w) {
+                Err(_) => return,
+                Ok((e, _)) => e.clone(),
+            };
+
+            if let Err(e) = transport.react(msg.channel_id, msg.id, emote).await {
+                eprintln!("FAILED TO REACT: {}", e);
+            }
+        }
+    }
+
+    // Nothing for messages without text, they'd match every memory
+    fn fetch_from_content(&mut self, content: String) -> Vec<&SclunerMessage> {
+        if content.trim().is_empty() {
+            return Vec::new();
+        }
+
+        self.messages
+            .iter()
+            .filter(|m| m.content.contains(&content))
+            .collect()
+    }
+
+    pub fn set_emojis(&mut self, emojis: Vec<ReactionType>) {
+        self.emoji_cache = Some(EmojiCache::new(emojis, Timestamp::now().unix_timestamp()));
+    }
+
+    // Fetches the guild's emojis if they aren't cached or are too old
+    async fn refresh_emojis(&mut self, transport: &dyn Transport) {
+        let now = Timestamp::now().unix_timestamp();
+        if self.emoji_cache.as_ref().is_some_and(|c| !c.is_stale(now)) {
+            return;
+        }
+
+        let emojis = match transport.guild_emojis(self.guild_id).await {
+            Ok(e) => e,
+            Err(e) => {
+                eprintln!("FAILED TO FETCH EMOJIS: {}", e);
+                // Try again after another TTL
+                self.emoji_cache.as_ref().map(|c| c.emojis().to_vec()).unwrap_or_default()
+            }
+        };
+
+        self.emoji_cache = Some(EmojiCache::new(emojis, now));
+    }
+
+    // What to react to content with and how likely each is
+    fn reaction_weights(&self, content: &str) -> Vec<(ReactionType, u32)> {
+        let mut emojis = self.emoji_pool();
+
+        // Standard emojis the guild used, even ones missing from the list
+        if self.unicode_emojis {
+            for emoji in self.reaction_stats.used_unicode() {
+                if !emojis.contains(&emoji) {
+                    emojis.push(emoji);
+                }
+            }
+        }
+
+        emojis
+            .into_iter()
+            .map(|e| {
+                let weight = self.reaction_stats.weight(&e, content);
+                (e, weight)
+            })
+            .collect()
+    }
+
+    // Everything that can be reacted with or appended, can be empty
+    pub fn emoji_pool(&self) -> Vec<ReactionType> {
+        let mut pool = self.emoji_cache.as_ref().map(|c| c.emojis().to_vec()).unwrap_or_default();
+
+        if self.unicode_emojis {
+            pool.extend(UNICODE_EMOJIS.iter().map(|e| ReactionType::Unicode(e.to_string())));
+        }
+
+        pool
+    }
+
+    // WordSwap reads it without being able to build it
+    fn prepare_vocabulary(&mut self) {
+        if self.vocabulary.is_none() && self.allowed_mutators.contains(&DefinedMutators::WordSwap) {
+            self.vocabulary = Some(Vocabulary::build(&self.messages));
+        }
+    }
+
+    fn remember(&mut self, message: SclunerMessage) {
+        if let Some(chain) = &mut self.markov {
+            chain.learn(&message.content);
+        }
+        if let Some(vocabulary) = &mut self.vocabulary {
+            vocabulary.learn(&message.content);
+        }
+
+        self.messages.push(message);
+
+        // message limit
+        if self.messages.len() > 2222 {
+            let remove_idx = self.rng.random_range(1000..=2222);
+            let removed = self.messages.swap_remove(remove_idx);
+
+            if let Some(chain) = &mut self.markov {
+                chain.forget(&removed.content);
+            }
+            if let Some(vocabulary) = &mut self.vocabulary {
+                vocabulary.forget(&removed.content);
+            }
+        }
+    }
+
+    fn forget_where(&mut self, forget: impl Fn(&SclunerMessage) -> bool) {
+        let (removed, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut self.messages)
+            .into_iter()
+            .partition(|m| forget(m));
+
+        self.messages = kept;
+
+        if let Some(chain) = &mut self.markov {
+            for m in &removed {
+                chain.forget(&m.content);
+            }
+        }
+        if let Some(vocabulary) = &mut self.vocabulary {
+            for m in &removed {
+                vocabulary.forget(&m.content);
+            }
+        }
+    }
+
+    fn delete_message_sender(&mut self, user_id: UserId) {
+        self.forget_where(|m| m.user_id == user_id);
+
+        for (_, sources) in self.reply_sources.iter_mut() {
+            sources.retain(|s| *s != user_id);
+        }
+    }
+
+    fn delete_message_content(&mut self, content: String) {
+        if content.trim().is_empty() {
+            return;
+        }
+
+        self.forget_where(|m| m.content.contains(content.as_str()));
+    }
+}
+
+pub struct SclunerInstance {
+    startup_instant: Instant,
+    backup_instant: Instant,
+    backup_storage: BackupStorageRef,
+    pub owners: Vec<UserId>,
+    pub guilds: HashMap<GuildId, SclunerGuild>,
+    pub whitelist: Vec<UserId>,
+    pub blacklist: Vec<UserId>,
+    // Makes every guild's randomness reproducible when set
+    seed: Option<u64>,
+}
+impl SclunerInstance {
+    pub fn new(backup_storage: BackupStorageRef, owners: Vec<UserId>) -> Self {
+        Self {
+            startup_instant: Instant::now(),
+            backup_instant: Instant::now(),
+            backup_storage,
+            owners,
+            guilds: HashMap::new(),
+            whitelist: Vec::new(),
+            blacklist: Vec::new(),
+            seed: None,
+        }
+    }
+
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+
+        for (guild_id, guild) in self.guilds.iter_mut() {
+            guild.rng = Self::guild_rng(self.seed, *guild_id);
+        }
+    }
+
+    // Each guild gets its own stream so they don't affect each other
+    fn guild_rng(seed: Option<u64>, guild_id: GuildId) -> SclunerRng {
+        match seed {
+            None => SclunerRng::from_os_rng(),
+            Some(seed) => SclunerRng::seed_from_u64(seed ^ guild_id.get()),
+        }
+    }
+
+    pub fn is_blacklisted(&self, guild_id: Option<GuildId>, user_id: UserId) -> bool {
+        self.blacklist.contains(&user_id)
+            || guild_id
+                .and_then(|g| self.guilds.get(&g))
+                .is_some_and(|g| g.blacklist.contains(&user_id))
+    }
+
+    pub fn guild_mut(&mut self, guild_id: GuildId) -> &mut SclunerGuild {
+        self.guilds
+            .entry(guild_id)
+            .or_insert_with(|| SclunerGuild::new(guild_id, Self::guild_rng(self.seed, guild_id)))
+    }
+
+    async fn maybe_mutate(input: String, transport: &dyn Transport, guild: &SclunerGuild, rng: &mut SclunerRng, sources: &mut Vec<UserId>) -> String {
+        let max = guild.max_mutators.unwrap_or(usize::MAX);
+
+        let mut stacker = input.clone();
+        let mut mutated = 0;
+
+        for (name, (chance, out_of), mutator) in guild.mutator_pipeline(rng) {
+            if mutated >= max {
+                break;
+            }
+
+            if !rng.random_ratio(chance, out_of) {
+                continue;
+            }
+
+            if let Some(s) = mutator.mutate(stacker.clone(), transport, guild, rng, sources).await {
+                println!("\tMutated by {}", name);
+                stacker = s;
+                mutated += 1;
+            }
+        }
+
+        stacker
+    }
+
+    pub async fn handle_message(&mut self, transport: &dyn Transport, msg: &Message) {
+        if msg.author.bot {
+            return;
+        }
+        if msg.content.contains("/unscule") || msg.content.contains("::SCL_") {
+            return;
+        }
+        let guild_id = match msg.guild_id {
+            None => return,
+            Some(g) => g,
+        };
+
+        let whitelisted = self.whitelist.contains(&msg.author.id);
+        let blacklisted = self.is_blacklisted(Some(guild_id), msg.author.id);
+        let guild = self.guild_mut(guild_id);
+        let now = msg.timestamp.unix_timestamp();
+
+        guild.maybe_wake(transport, now).await;
+        if guild.is_asleep(msg.channel_id, now) {
+            return;
+        }
+
+        if guild.can_speak(msg.channel_id) {
+            guild.maybe_react_random(transport, msg).await;
+            println!("\tReact proc done.");
+
+            guild.maybe_reroll_proc(now);
+
+            // reply if we procced, or they're pinging it or replying to it
+            if guild.roll_reply(msg.channel_id, now)
+                || msg.mentions_user_id(transport.current_user_id())
+            {
+                println!("\tReply proc!");
+                guild.send_random(transport, msg.channel_id, &msg.content).await;
+                println!("\tReply proc done.");
+
+                if guild.proc_reroll == ProcReroll::AfterReply {
+                    guild.roll_proc(now);
+                }
+            }
+        }
+
+        if guild.can_learn(msg.channel_id)
+            && msg.mentions.is_empty()
+            && !blacklisted
+            && whitelisted
+            && !msg.content.is_empty()
+            && msg.content.len() < 2000
+            && msg.content.split_whitespace().count() < 30
+        {
+            guild.reaction_stats.record_text(&msg.content);
+            guild.remember(SclunerMessage::new(msg));
+        }
+    }
+
+    // Someone reacted with emoji to a message saying content
+    pub fn handle_reaction(&mut self, guild_id: GuildId, channel_id: ChannelId, user_id: UserId, emoji: &ReactionType, content: &str) {
+        if self.learns_reaction(guild_id, channel_id, user_id) {
+            self.guild_mut(guild_id).reaction_stats.record(emoji, content);
+        }
+    }
+
+    // Checked before fetching what was reacted to, most reactions don't need it
+    pub fn learns_reaction(&mut self, guild_id: GuildId, channel_id: ChannelId, user_id: UserId) -> bool {
+        self.whitelist.contains(&user_id)
+            && !self.is_blacklisted(Some(guild_id), user_id)
+            && self.guild_mut(guild_id).can_learn(channel_id)
+    }
+
+    pub async fn save_backup(&self) {
+        let backup = SclunerBackup::new(
+            &self.guilds,
+            &self.whitelist,
+            &self.blacklist,
+        );
+
+        let data = match backup.to_bytes() {
+            Ok(d) => d,
+            Err(e) => {
+                eprintln!("FAILED TO BACKUP : SERIALISATION UNSUCCESSFUL:{}", e);
+                return;
+            }
+        };
+
+        // Store the backup file
+        if let Err(e) = self.backup_storage.save(data).await {
+            eprintln!("FAILED TO BACKUP : FILES COULDN'T BE STORED:{}", e);
+            return;
+        }
+
+        println!("BACKUP SUCCESSFUL!");
+    }
+
+    pub fn load_backup(&mut self, load: SclunerBackup) {
+        let guilds = load
+            .guilds_keys
+            .into_iter()
+            .zip(load.guilds_values)
+            .collect();
+
+        self.backup_instant = Instant::now();
+        self.guilds = guilds;
+        self.whitelist = load.whitelist;
+        self.blacklist = load.blacklist;
+
+        if let Some(seed) = self.seed {
+            self.set_seed(seed);
+        }
+    }
+}
+
+pub async fn event_handler(
+    ctx: &serenity::Context,
+    event: &FullEvent,
+    _framework: poise::FrameworkContext<'_, SclunerRef, Error>,
+    data: &SclunerRef,
+) -> Result<(), Error> {
+    match event {
+        FullEvent::Ready {
+            data_about_bot: bot,
+            ..
+        } => {
+            println!("HEY {}!", bot.user.name.to_ascii_uppercase());
+
+            let mut data = data.lock().await;
+
+            let backup_bytes = match data.backup_storage.load_latest().await {
+                Ok(Some(b)) => b,
+                // We don't care
+                Ok(None) => return Ok(()),
+                Err(e) => {
+                    eprintln!("FAILED TO LOAD BACKUP : {}", e);
+                    return Ok(());
+                }
+            };
+
+            let backup = match SclunerBackup::from_bytes(&backup_bytes) {
+                Ok(v) => v,
+                Err(e) => {
+                    eprintln!("FAILED TO LOAD BACKUP : {}", e);
+                    return Ok(());
+                }
+            };
+
+            data.load_backup(backup);
+            drop(data);
+            println!("Loaded last backup!");
+        }
+
+        FullEvent::Message { new_message: msg } => {
+            let mut data = data.lock().await;
+
+            // auto backup per 12 hours
+            if data.backup_instant.elapsed().as_secs() >= 43200 {
+                data.save_backup().await;
+                data.backup_instant = Instant::now();
+            }
+
+            data.handle_message(&SerenityTransport { ctx }, msg).await;
+            drop(data);
+        }
+
+        FullEvent::ReactionAdd { add_reaction: reaction } => {
+            let (guild_id, user_id) = match (reaction.guild_id, reaction.user_id) {
+                (Some(g), Some(u)) => (g, u),
+                _ => return Ok(()),
+            };
+            if user_id == ctx.cache.current_user().id || reaction.member.as_ref().is_some_and(|m| m.user.bot) {
+                return Ok(());
+            }
+
+            if !data.lock().await.learns_reaction(guild_id, reaction.channel_id, user_id) {
+                return Ok(());
+            }
+
+            // Messages aren't cached so this is a request, still worth counting the emoji if it fails
+            let content = match reaction.message(ctx).await {
+                Ok(m) => m.content,
+                Err(e) => {
+                    eprintln!("FAILED TO FETCH REACTED MESSAGE: {}", e);
+                    String::new()
+                }
+            };
+
+            data.lock().await.handle_reaction(guild_id, reaction.channel_id, user_id, &reaction.emoji, &content);
+        }
+
+        FullEvent::GuildEmojisUpdate { guild_id, current_state } => {
+            let emojis = current_state.values().cloned().map(ReactionType::from).collect();
+            data.lock().await.guild_mut(*guild_id).set_emojis(emojis);
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+pub fn commands() -> Vec<poise::Command<SclunerRef, Error>> {
+    vec![
+        // USER
+        delete_content(),
+        delete_content_menu(),
+        info_content(),
+        info_content_menu(),
+        info_proc(),
+        info_channel(),
+        info_sleep(),
+        info_emojis(),
+        info(),
+        export_data(),
+        // MODS
+        delete_user(),
+        proc(),
+        proc_reroll(),
+        adaptive_rate(),
+        sleep(),
+        nap(),
+        timezone(),
+        quiet_hours_add(),
+        quiet_hours_remove(),
+        channel_defaults(),
+        channel_learn(),
+        channel_speak(),
+        channel_proc(),
+        channel_sleep(),
+        reply_mode(),
+        unicode_emojis(),
+        mutator_list(),
+        mutator_enable(),
+        mutator_disable(),
+        mutator_chance(),
+        mutator_order(),
+        max_mutators(),
+        regex_mutator_add(),
+        regex_mutator_remove(),
+        pronoun_set_list(),
+        pronoun_set_add(),
+        pronoun_set_remove(),
+        markov(),
+        mod_role(),
+        mod_permission(),
+        blacklist(),
+        unblacklist(),
+        blacklist_list(),
+        // DEV
+        moderator(),
+        whitelist(),
+        backup_send(),
+        backup_load(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::backup_storage::LocalDirStorage;
+    use crate::memory_transport::{MemoryTransport, TransportEvent};
+
+    const BOT: UserId = UserId::new(1);
+    const USER: UserId = UserId::new(2);
+    const GUILD: GuildId = GuildId::new(3);
+    const CHANNEL: ChannelId = ChannelId::new(4);
+
+    fn instance() -> SclunerInstance {
+        let storage = Arc::new(LocalDirStorage { dir: PathBuf::new() });
+        let mut instance = SclunerInstance::new(storage, Vec::new());
+        instance.set_seed(0);
+
+        let guild = instance.guild_mut(GUILD);
+        guild.allowed_mutators = Vec::new();
+        guild.min_proc = 0;
+        guild.max_proc = 0;
+        guild.proc = 0;
+
+        instance
+    }
+
+    fn message(content: &str) -> Message {
+        let mut msg = Message::default();
+        msg.id = MessageId::new(100);
+        msg.channel_id = CHANNEL;
+        msg.guild_id = Some(GUILD);
+        msg.author.id = USER;
+        msg.content = content.to_string();
+        msg
+    }
+
+    fn mention(content: &str) -> Message {
+        let mut msg = message(content);
+        let mut bot = User::default();
+        bot.id = BOT;
+        msg.mentions.push(bot);
+        msg
+    }
+
+    fn sent_contents(events: &[TransportEvent]) -> Vec<String> {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                TransportEvent::Sent { content, .. } | TransportEvent::Replied { content, .. } => Some(content.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn remembers_whitelisted_messages_only() {
+        let transport = MemoryTransport::new(BOT);
+        let mut instance = instance();
+
+        async_std::task::block_on(instance.handle_message(&transport, &message("not yet")));
+        assert!(instance.guilds[&GUILD].messages.is_empty());
+
+        instance.whitelist.push(USER);
+        async_std::task::block_on(instance.handle_message(&transport, &message("hello there")));
+        assert_eq!(instance.guilds[&GUILD].messages.len(), 1);
+
+        instance.guild_mut(GUILD).blacklist.push(USER);
+        async_std::task::block_on(instance.handle_message(&transport, &message("banned")));
+        assert_eq!(instance.guilds[&GUILD].messages.len(), 1);
+    }
+
+    #[test]
+    fn replies_with_memories_when_mentioned() {
+        let transport = MemoryTransport::new(BOT);
+        let mut instance = instance();
+        instance.guild_mut(GUILD).remember(SclunerMessage { user_id: USER, content: "hello there".to_string() });
+
+        async_std::task::block_on(instance.handle_message(&transport, &mention("hey bot")));
+
+        let events = transport.take_events();
+        assert_eq!(events[0], TransportEvent::Typing { channel_id: CHANNEL });
+
+        let sent = sent_contents(&events);
+        assert!(!sent.is_empty());
+        assert!(sent.iter().all(|c| c == "hello there"));
+    }
+
+    #[test]
+    fn rerolls_proc_within_range() {
+        let transport = MemoryTransport::new(BOT);
+        let mut instance = instance();
+        let guild = instance.guild_mut(GUILD);
+        guild.remember(SclunerMessage { user_id: USER, content: "hello there".to_string() });
+        guild.min_proc = 5;
+        guild.max_proc = 7;
+
+        async_std::task::block_on(instance.handle_message(&transport, &mention("hey bot")));
+        assert!((5..=7).contains(&instance.guilds[&GUILD].proc));
+
+        let guild = instance.guild_mut(GUILD);
+        guild.proc_reroll = ProcReroll::Every(60);
+        guild.proc_rolled_at = 0;
+        guild.min_proc = 0;
+        guild.max_proc = 0;
+
+        let mut later = message("later");
+        later.timestamp = Timestamp::from_unix_timestamp(59).unwrap();
+        async_std::task::block_on(instance.handle_message(&transport, &later));
+        assert!((5..=7).contains(&instance.guilds[&GUILD].proc));
+
+        later.timestamp = Timestamp::from_unix_timestamp(60).unwrap();
+        async_std::task::block_on(instance.handle_message(&transport, &later));
+        assert_eq!(instance.guilds[&GUILD].proc, 0);
+    }
+
+    #[test]
+    fn follows_channel_overrides() {
+        let transport = MemoryTransport::new(BOT);
+        let mut instance = instance();
+        instance.whitelist.push(USER);
+        let guild = instance.guild_mut(GUILD);
+        guild.remember(SclunerMessage { user_id: USER, content: "hello there".to_string() });
+        guild.channels.insert(CHANNEL, SclunerChannel { learn: Some(false), speak: Some(false), ..Default::default() });
+
+        async_std::task::block_on(instance.handle_message(&transport, &mention("hey bot")));
+        async_std::task::block_on(instance.handle_message(&transport, &message("don't learn me")));
+        assert!(transport.take_events().is_empty());
+        assert_eq!(instance.guilds[&GUILD].messages.len(), 1);
+
+        // Only this channel is allowed
+        let guild = instance.guild_mut(GUILD);
+        guild.learn_by_default = false;
+        guild.channels.insert(CHANNEL, SclunerChannel { learn: Some(true), ..Default::default() });
+
+        async_std::task::block_on(instance.handle_message(&transport, &message("learn me")));
+        assert_eq!(instance.guilds[&GUILD].messages.len(), 2);
+
+        instance.guild_mut(GUILD).channels.get_mut(&CHANNEL).unwrap().asleep = true;
+        async_std::task::block_on(instance.handle_message(&transport, &mention("hey bot")));
+        assert!(transport.take_events().is_empty());
+    }
+
+    #[test]
+    fn stays_quiet_while_asleep() {
+        let transport = MemoryTransport::new(BOT);
+        let mut instance = instance();
+        instance.whitelist.push(USER);
+        instance.guild_mut(GUILD).remember(SclunerMessage { user_id: USER, content: "hello there".to_string() });
+        instance.guild_mut(GUILD).asleep = true;
+
+        async_std::task::block_on(instance.handle_message(&transport, &mention("hey bot")));
+
+        assert!(transport.take_events().is_empty());
+        assert_eq!(instance.guilds[&GUILD].messages.len(), 1);
+    }
+
+    #[test]
+    fn wakes_up_after_a_nap() {
+        let transport = MemoryTransport::new(BOT);
+        let mut instance = instance();
+        instance.guild_mut(GUILD).remember(SclunerMessage { user_id: USER, content: "hello there".to_string() });
+        instance.guild_mut(GUILD).nap = Some(Nap { until: 100, channel_id: CHANNEL });
+
+        let mut msg = mention("hey bot");
+        msg.timestamp = Timestamp::from_unix_timestamp(50).unwrap();
+        async_std::task::block_on(instance.handle_message(&transport, &msg));
+        assert!(transport.take_events().is_empty());
+
+        msg.timestamp = Timestamp::from_unix_timestamp(100).unwrap();
+        async_std::task::block_on(instance.handle_message(&transport, &msg));
+        let sent = sent_contents(&transport.take_events());
+        assert_eq!(sent[..2], ["Good morning!", "hello there"]);
+        assert!(instance.guilds[&GUILD].nap.is_none());
+
+        // Quiet hours took over while it napped
+        let guild = instance.guild_mut(GUILD);
+        guild.nap = Some(Nap { until: 100, channel_id: CHANNEL });
+        guild.quiet_hours.push(QuietHours { start: 0, end: 60 });
+        async_std::task::block_on(instance.handle_message(&transport, &msg));
+        assert!(transport.take_events().is_empty());
+        assert!(instance.guilds[&GUILD].nap.is_none());
+    }
+
+    #[test]
+    fn runs_mutators_in_order_up_to_max() {
+        let mut transport = MemoryTransport::new(BOT);
+        transport.emojis.insert(GUILD, vec![ReactionType::Unicode("🐸".to_string())]);
+        let mut instance = instance();
+
+        let guild = instance.guild_mut(GUILD);
+        guild.remember(SclunerMessage { user_id: USER, content: "he said hi".to_string() });
+        guild.allowed_mutators = vec![DefinedMutators::Misgendering, DefinedMutators::AppendEmote];
+        guild.mutator_chances.insert(DefinedMutators::AppendEmote, (1, 1));
+        guild.mutator_chances.insert(DefinedMutators::Misgendering, (1, 1));
+        guild.mutator_order = Some(vec![DefinedMutators::AppendEmote]);
+        guild.max_mutators = Some(1);
+
+        async_std::task::block_on(instance.handle_message(&transport, &mention("hey bot")));
+
+        let sent = sent_contents(&transport.take_events());
+        assert!(!sent.is_empty());
+        assert!(sent.iter().all(|c| c == "he said hi 🐸"));
+    }
+
+    #[test]
+    fn applies_regex_mutators() {
+        let transport = MemoryTransport::new(BOT);
+        let mut instance = instance();
+
+        let guild = instance.guild_mut(GUILD);
+        guild.remember(SclunerMessage { user_id: USER, content: "i like cats".to_string() });
+        guild.regex_mutators.push(RegexMutator::new("dogs".to_string(), r"\bcats?\b".to_string(), "dogs".to_string(), 1, 1).unwrap());
+
+        async_std::task::block_on(instance.handle_message(&transport, &mention("hey bot")));
+
+        let sent = sent_contents(&transport.take_events());
+        assert!(!sent.is_empty());
+        assert!(sent.iter().all(|c| c == "i like dogs"));
+    }
+
+    #[test]
+    fn rejects_bad_regex_mutators() {
+        let new = |pattern: &str| RegexMutator::new("bad".to_string(), pattern.to_string(), String::new(), 1, 1);
+
+        assert!(new("(unclosed").is_err());
+        assert!(new(&"a".repeat(500)).is_err());
+        // Compiles to something huge
+        assert!(new(r"\w{1000}\w{1000}").is_err());
+        assert!(new(r"\w+").is_ok());
+    }
+
+    #[test]
+    fn same_seed_same_replies() {
+        let run = |seed: u64| {
+            let mut transport = MemoryTransport::new(BOT);
+            transport.emojis.insert(GUILD, vec![ReactionType::Unicode("🐸".to_string())]);
+            let mut instance = instance();
+            instance.set_seed(seed);
+            instance.whitelist.push(USER);
+
+            let guild = instance.guild_mut(GUILD);
+            guild.allowed_mutators = DefinedMutators::all();
+            guild.mutator_chances = DefinedMutators::all().into_iter().map(|m| (m, (1, 2))).collect();
+            guild.proc = 1;
+            guild.proc_out_of = 2;
+
+            let lines = ["he went to the shop", "she said they would call", "the cat sat on his hat", "what did it do", "hey bot"];
+            let mut events = Vec::new();
+            for line in lines.iter().cycle().take(40) {
+                async_std::task::block_on(instance.handle_message(&transport, &message(line)));
+                events.extend(transport.take_events());
+            }
+            events
+        };
+
+        assert_eq!(run(42), run(42));
+        assert_ne!(run(42), run(43));
+    }
+
+    #[test]
+    fn replays_a_reply_from_its_seed() {
+        let transport = MemoryTransport::new(BOT);
+        let mut instance = instance();
+        let guild = instance.guild_mut(GUILD);
+        for content in ["one fish", "two fish", "red fish", "blue fish"] {
+            guild.remember(SclunerMessage { user_id: USER, content: content.to_string() });
+        }
+        guild.reply_mode = ReplyMode::Markov;
+        guild.markov_order = 1;
+
+        async_std::task::block_on(guild.send_seeded(&transport, CHANNEL, "", 1234));
+        let first = sent_contents(&transport.take_events());
+        async_std::task::block_on(guild.send_seeded(&transport, CHANNEL, "", 1234));
+
+        assert_eq!(first, sent_contents(&transport.take_events()));
+    }
+
+    #[test]
+    fn remembers_who_spliced_replies_came_from() {
+        let transport = MemoryTransport::new(BOT);
+        let mut instance = instance();
+        let other = UserId::new(5);
+
+        let guild = instance.guild_mut(GUILD);
+        guild.remember(SclunerMessage { user_id: USER, content: "the cat sat on the mat".to_string() });
+        guild.remember(SclunerMessage { user_id: other, content: "a dog ran in the park".to_string() });
+        guild.allowed_mutators = vec![DefinedMutators::MessageSplicer];
+        guild.mutator_chances.insert(DefinedMutators::MessageSplicer, (1, 1));
+
+        async_std::task::block_on(instance.handle_message(&transport, &mention("hey bot")));
+
+        let guild = &instance.guilds[&GUILD];
+        for event in transport.take_events() {
+            if let TransportEvent::Sent { message_id, .. } | TransportEvent::Replied { message_id, .. } = event {
+                let sources = guild.sources_of(message_id).unwrap();
+                assert!(!sources.is_empty());
+                assert!(sources.iter().all(|s| *s == USER || *s == other));
+            }
+        }
+    }
+
+    #[test]
+    fn ignores_bots_and_commands() {
+        let transport = MemoryTransport::new(BOT);
+        let mut instance = instance();
+        instance.whitelist.push(USER);
+
+        let mut from_bot = mention("beep");
+        from_bot.author.bot = true;
+        async_std::task::block_on(instance.handle_message(&transport, &from_bot));
+        async_std::task::block_on(instance.handle_message(&transport, &message("::SCL_info")));
+
+        assert!(transport.take_events().is_empty());
+        assert!(instance.guilds[&GUILD].messages.is_empty());
+    }
+
+    #[test]
+    fn caches_emojis_until_they_update() {
+        let frog = ReactionType::Unicode("🐸".to_string());
+        let duck = ReactionType::Unicode("🦆".to_string());
+        let mut transport = MemoryTransport::new(BOT);
+        transport.emojis.insert(GUILD, vec![frog.clone()]);
+        let mut instance = instance();
+        let guild = instance.guild_mut(GUILD);
+
+        async_std::task::block_on(guild.refresh_emojis(&transport));
+        transport.emojis.insert(GUILD, vec![duck.clone()]);
+        async_std::task::block_on(guild.refresh_emojis(&transport));
+        assert_eq!(guild.emoji_pool(), [frog]);
+
+        guild.set_emojis(vec![duck.clone()]);
+        assert_eq!(guild.emoji_pool(), [duck]);
+
+        guild.unicode_emojis = true;
+        assert_eq!(guild.emoji_pool().len(), 1 + emoji_cache::UNICODE_EMOJIS.len());
+    }
+
+    #[test]
+    fn never_reacts_without_emojis() {
+        let transport = MemoryTransport::new(BOT);
+        let mut instance = instance();
+
+        for _ in 0..200 {
+            async_std::task::block_on(instance.handle_message(&transport, &message("hello there")));
+        }
+
+        assert!(!transport.take_events().iter().any(|e| matches!(e, TransportEvent::Reacted { .. })));
+    }
+
+    #[test]
+    fn reacts_with_what_the_guild_uses() {
+        let frog = ReactionType::Unicode("🐸".to_string());
+        let duck = ReactionType::Custom { animated: false, id: EmojiId::new(7), name: Some("duck".to_string()) };
+        let laugh = ReactionType::Unicode("😂".to_string());
+        let mut transport = MemoryTransport::new(BOT);
+        transport.emojis.insert(GUILD, vec![frog.clone(), duck.clone()]);
+        let mut instance = instance();
+
+        // Not whitelisted yet
+        instance.handle_reaction(GUILD, CHANNEL, USER, &duck, "quack");
+        assert!(instance.guilds[&GUILD].reaction_stats.totals.is_empty());
+
+        instance.whitelist.push(USER);
+        assert!(instance.learns_reaction(GUILD, CHANNEL, USER));
+        instance.guild_mut(GUILD).channels.insert(CHANNEL, SclunerChannel { learn: Some(false), ..Default::default() });
+        assert!(!instance.learns_reaction(GUILD, CHANNEL, USER));
+        instance.guild_mut(GUILD).channels.clear();
+
+        for _ in 0..50 {
+            instance.handle_reaction(GUILD, CHANNEL, USER, &duck, "quack");
+            instance.handle_reaction(GUILD, CHANNEL, USER, &laugh, "quack");
+        }
+
+        let mut reactions: HashMap<ReactionType, u32> = HashMap::new();
+        for _ in 0..400 {
+            async_std::task::block_on(instance.handle_message(&transport, &message("quack")));
+        }
+        for event in transport.take_events() {
+            if let TransportEvent::Reacted { reaction, .. } = event {
+                *reactions.entry(reaction).or_insert(0) += 1;
+            }
+        }
+
+        let (ducks, frogs) = (reactions.get(&duck).copied().unwrap_or(0), reactions.get(&frog).copied().unwrap_or(0));
+        assert!(ducks > frogs * 10, "{} ducks, {} frogs", ducks, frogs);
+        // Standard emojis are off, learned ones too
+        assert!(!reactions.contains_key(&laugh));
+
+        instance.guild_mut(GUILD).unicode_emojis = true;
+        assert!(instance.guilds[&GUILD].reaction_weights("quack").iter().any(|(e, w)| *e == laugh && *w > 1));
+    }
+
+    #[test]
+    fn empty_content_matches_nothing() {
+        let mut instance = instance();
+        let guild = instance.guild_mut(GUILD);
+        guild.remember(SclunerMessage { user_id: USER, content: "hello there".to_string() });
+        guild.remember(SclunerMessage { user_id: USER, content: "general kenobi".to_string() });
+
+        for content in ["", "  "] {
+            assert!(guild.fetch_from_content(content.to_string()).is_empty());
+            guild.delete_message_content(content.to_string());
+        }
+        assert_eq!(guild.messages.len(), 2);
+
+        guild.delete_message_content("there".to_string());
+        assert_eq!(guild.messages.len(), 1);
+    }
+
+    #[test]
+    fn survives_old_proc_values() {
+        let transport = MemoryTransport::new(BOT);
+        let mut old = instance();
+        let guild = old.guild_mut(GUILD);
+        guild.remember(SclunerMessage { user_id: USER, content: "hello there".to_string() });
+        (guild.min_proc, guild.max_proc, guild.proc_out_of, guild.proc) = (10, 30, 18, 25);
+        guild.proc_reroll = ProcReroll::Every(0);
+        guild.proc_rolled_at = 0;
+
+        let backup = SclunerBackup::new(&old.guilds, &[], &[]);
+        let mut instance = instance();
+        instance.load_backup(SclunerBackup::from_bytes(&backup.to_bytes().unwrap()).unwrap());
+
+        for _ in 0..20 {
+            async_std::task::block_on(instance.handle_message(&transport, &message("hi")));
+            assert!(instance.guilds[&GUILD].proc <= 18);
+        }
+
+        instance.guild_mut(GUILD).proc_out_of = 0;
+        for _ in 0..20 {
+            async_std::task::block_on(instance.handle_message(&transport, &message("hi")));
+        }
+    }
+
+    #[test]
+    fn sleep_wakes_from_naps_and_toggles_after() {
+        let mut instance = instance();
+        let guild = instance.guild_mut(GUILD);
+
+        guild.nap = Some(Nap { until: 100, channel_id: CHANNEL });
+        assert!(!guild.toggle_sleep(50));
+        assert!(guild.nap.is_none());
+
+        // The nap ran out without anyone talking
+        guild.nap = Some(Nap { until: 100, channel_id: CHANNEL });
+        assert!(guild.toggle_sleep(150));
+        assert!(guild.nap.is_none());
+        assert!(!guild.toggle_sleep(150));
+    }
+}
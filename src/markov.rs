@@ -0,0 +1,169 @@
+use std::collections::{BTreeMap, HashMap};
+
+use rand::prelude::IndexedRandom;
+use rand::Rng;
+
+use crate::SclunerMessage;
+
+// Marks the start and end of a message, tokens can never be empty so this can't collide
+const BOUNDARY: &str = "";
+
+#[derive(Clone, Default)]
+pub struct MarkovChain {
+    order: usize,
+    transitions: HashMap<Vec<String>, BTreeMap<String, u32>>,
+}
+
+impl MarkovChain {
+    pub fn new(order: usize) -> Self {
+        Self {
+            order: order.max(1),
+            transitions: HashMap::new(),
+        }
+    }
+
+    pub fn build(order: usize, messages: &[SclunerMessage]) -> Self {
+        let mut chain = Self::new(order);
+
+        for message in messages {
+            chain.learn(&message.content);
+        }
+
+        chain
+    }
+
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    // Every (state, next token) pair in the content
+    fn windows(&self, content: &str) -> Vec<(Vec<String>, String)> {
+        let mut tokens = vec![BOUNDARY.to_string(); self.order];
+        tokens.extend(content.split_whitespace().map(str::to_string));
+        tokens.push(BOUNDARY.to_string());
+
+        tokens
+            .windows(self.order + 1)
+            .map(|w| (w[..self.order].to_vec(), w[self.order].clone()))
+            .collect()
+    }
+
+    pub fn learn(&mut self, content: &str) {
+        if content.split_whitespace().next().is_none() {
+            return;
+        }
+
+        for (state, next) in self.windows(content) {
+            *self.transitions.entry(state).or_default().entry(next).or_insert(0) += 1;
+        }
+    }
+
+    pub fn forget(&mut self, content: &str) {
+        for (state, next) in self.windows(content) {
+            let Some(nexts) = self.transitions.get_mut(&state) else { continue };
+
+            if let Some(count) = nexts.get_mut(&next) {
+                *count -= 1;
+                if *count == 0 {
+                    nexts.remove(&next);
+                }
+            }
+
+            if nexts.is_empty() {
+                self.transitions.remove(&state);
+            }
+        }
+    }
+
+    // Walks the chain from a message start until it hits a message end or max_len tokens
+    pub fn generate(&self, rng: &mut impl Rng, max_len: usize) -> Option<String> {
+        let mut state = vec![BOUNDARY.to_string(); self.order];
+        let mut output: Vec<String> = Vec::new();
+
+        while output.len() < max_len {
+            let nexts: Vec<(&String, &u32)> = match self.transitions.get(&state) {
+                None => break,
+                Some(n) => n.iter().collect(),
+            };
+
+            let next = match nexts.choose_weighted(rng, |(_, count)| **count) {
+                Err(_) => break,
+                Ok((next, _)) => (*next).clone(),
+            };
+
+            if next == BOUNDARY {
+                break;
+            }
+
+            state.remove(0);
+            state.push(next.clone());
+            output.push(next);
+        }
+
+        if output.is_empty() {
+            return None;
+        }
+
+        Some(output.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SclunerRng;
+    use rand::SeedableRng;
+
+    fn message(content: &str) -> SclunerMessage {
+        SclunerMessage { user_id: 1.into(), content: content.to_string() }
+    }
+
+    #[test]
+    fn forgetting_undoes_learning() {
+        let mut chain = MarkovChain::build(2, &[message("the cat sat on the mat")]);
+
+        chain.learn("the dog sat on the rug");
+        chain.forget("the dog sat on the rug");
+        chain.forget("the cat sat on the mat");
+
+        assert!(chain.transitions.is_empty());
+    }
+
+    #[test]
+    fn empty_chains_generate_nothing() {
+        let mut rng = SclunerRng::seed_from_u64(0);
+
+        assert_eq!(MarkovChain::new(2).generate(&mut rng, 30), None);
+        assert_eq!(MarkovChain::build(2, &[message("   ")]).generate(&mut rng, 30), None);
+        assert_eq!(MarkovChain::build(2, &[message("hi")]).generate(&mut rng, 0), None);
+    }
+
+    #[test]
+    fn generates_within_max_len() {
+        let chain = MarkovChain::build(1, &[message("a a a a a a a a a a a a a a a a a a a a")]);
+
+        for seed in 0..20 {
+            let output = chain.generate(&mut SclunerRng::seed_from_u64(seed), 5).unwrap();
+            assert!(output.split_whitespace().count() <= 5);
+        }
+    }
+
+    #[test]
+    fn follows_learned_states() {
+        // With order 2 every state has one way forward
+        let chain = MarkovChain::build(2, &[message("one two three four"), message("zero two five six")]);
+        assert_eq!(chain.order(), 2);
+
+        for seed in 0..20 {
+            let output = chain.generate(&mut SclunerRng::seed_from_u64(seed), 30).unwrap();
+            assert!(output == "one two three four" || output == "zero two five six", "{}", output);
+        }
+
+        // Order 1 only looks at "two" and can cross over
+        let chain = MarkovChain::build(1, &[message("one two three four"), message("zero two five six")]);
+        let outputs: Vec<String> = (0..50)
+            .filter_map(|seed| chain.generate(&mut SclunerRng::seed_from_u64(seed), 30))
+            .collect();
+        assert!(outputs.iter().any(|o| o == "one two five six" || o == "zero two three four"));
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use poise::serenity_prelude::*;
+
+use crate::Error;
+use crate::transport::{Transport, TypingGuard};
+
+// Records everything instead of sending it anywhere, for tests and simulations
+pub struct MemoryTransport {
+    pub bot_id: UserId,
+    pub emojis: HashMap<GuildId, Vec<ReactionType>>,
+    events: Mutex<Vec<TransportEvent>>,
+    last_messages: Mutex<HashMap<ChannelId, MessageId>>,
+    next_id: AtomicU64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TransportEvent {
+    Typing { channel_id: ChannelId },
+    Sent { channel_id: ChannelId, message_id: MessageId, content: String },
+    Replied { channel_id: ChannelId, message_id: MessageId, reply_to: MessageId, content: String },
+    Reacted { channel_id: ChannelId, message_id: MessageId, reaction: ReactionType },
+}
+
+impl MemoryTransport {
+    pub fn new(bot_id: UserId) -> Self {
+        Self {
+            bot_id,
+            emojis: HashMap::new(),
+            events: Mutex::new(Vec::new()),
+            last_messages: Mutex::new(HashMap::new()),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    // Lets the transport know someone else posted, so the bot's messages can get left behind
+    pub fn observe(&self, channel_id: ChannelId, message_id: MessageId) {
+        self.last_messages.lock().unwrap().insert(channel_id, message_id);
+    }
+
+    pub fn take_events(&self) -> Vec<TransportEvent> {
+        std::mem::take(&mut *self.events.lock().unwrap())
+    }
+
+    fn record(&self, event: TransportEvent) {
+        self.events.lock().unwrap().push(event);
+    }
+
+    fn post(&self, channel_id: ChannelId) -> MessageId {
+        let message_id = MessageId::new(self.next_id.fetch_add(1, Ordering::Relaxed));
+        self.observe(channel_id, message_id);
+        message_id
+    }
+}
+
+#[async_trait] // Keeps everything in memory
+impl Transport for MemoryTransport {
+    fn current_user_id(&self) -> UserId {
+        self.bot_id
+    }
+
+    fn start_typing(&self, channel_id: ChannelId) -> TypingGuard {
+        self.record(TransportEvent::Typing { channel_id });
+        Box::new(())
+    }
+
+    async fn wait(&self, _: Duration) {}
+
+    async fn send_message(&self, channel_id: ChannelId, content: String) -> Result<MessageId, Error> {
+        let message_id = self.post(channel_id);
+        self.record(TransportEvent::Sent { channel_id, message_id, content });
+        Ok(message_id)
+    }
+
+    async fn reply(&self, channel_id: ChannelId, message_id: MessageId, content: String) -> Result<MessageId, Error> {
+        let reply_to = message_id;
+        let message_id = self.post(channel_id);
+        self.record(TransportEvent::Replied { channel_id, message_id, reply_to, content });
+        Ok(message_id)
+    }
+
+    async fn react(&self, channel_id: ChannelId, message_id: MessageId, reaction: ReactionType) -> Result<(), Error> {
+        self.record(TransportEvent::Reacted { channel_id, message_id, reaction });
+        Ok(())
+    }
+
+    async fn guild_emojis(&self, guild_id: GuildId) -> Result<Vec<ReactionType>, Error> {
+        Ok(self.emojis.get(&guild_id).cloned().unwrap_or_default())
+    }
+
+    async fn last_message_id(&self, channel_id: ChannelId) -> Result<Option<MessageId>, Error> {
+        Ok(self.last_messages.lock().unwrap().get(&channel_id).copied())
+    }
+}
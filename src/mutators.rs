@@ -1,169 +1,934 @@
-use std::sync::Arc;
-
-use poise::serenity_prelude::*;
-use rand::prelude::IndexedRandom;
-use rand::{rng, Rng};
-use serde::{Serialize, Deserialize};
-
-use crate::SclunerGuild;
-
-pub type MutatorRef = Arc<dyn MessageMutator + Send + Sync>;
-
-#[async_trait]
-pub trait MessageMutator: Send + Sync {
-    async fn mutate(&self, input: String, ctx: &Context, guild: &SclunerGuild) -> Option<String>;
-}
-
-#[derive(Serialize, Deserialize, Clone)]
-pub enum DefinedMutators {
-    AppendEmote,
-    MessageSplicer,
-    Misgendering
-}
-
-impl DefinedMutators {
-    pub fn default_allowed() -> Vec<Self> {
-        vec![
-            Self::AppendEmote,
-            Self::MessageSplicer,
-            Self::Misgendering
-        ]
-    }
-
-    pub fn to_mutators(allowed: &Vec<Self>) -> Vec<MutatorRef> {
-        allowed.iter().map(|mutator| {
-            match mutator {
-                DefinedMutators::AppendEmote => Arc::new(AppendEmote) as MutatorRef,
-                DefinedMutators::MessageSplicer => Arc::new(MessageSplicer) as MutatorRef,
-                DefinedMutators::Misgendering => Arc::new(Misgendering) as MutatorRef,
-            }
-        }).collect()
-    }
-}
-
-// DEFINITIONS
-pub struct AppendEmote;
-pub struct MessageSplicer;
-pub struct Misgendering;
-
-// IMPLEMENTATIONS
-#[async_trait] // Appends an emote at the end of the input
-impl MessageMutator for AppendEmote {
-    async fn mutate(&self, input: String, ctx: &Context, guild: &SclunerGuild) -> Option<String> {
-        if !rng().random_ratio(1, 16) { return None }
-
-        let emotes = guild.guild_id.emojis(ctx.http()).await.unwrap();
-        let emote = emotes.choose(&mut rng()).unwrap();
-
-        Some(format!("{} {}", input, emote))
-    }
-}
-
-// TODO: Improve message splicer
-#[async_trait] // Splices the input and another message together
-impl MessageMutator for MessageSplicer {
-    async fn mutate(&self, input: String, _: &Context, guild: &SclunerGuild) -> Option<String> {
-        if !rng().random_ratio(1, 16) { return None }
-
-        let input_tokens = input.split_whitespace();
-        let random_tokens = guild.messages.choose(&mut rng()).unwrap().content.split_whitespace();
-
-        let input_len = input.len();
-        let random_len = random_tokens.clone().count();
-
-        let splicing_input = input_len > random_len;
-
-        // Take most from input if its larger
-        if splicing_input {
-            let input_range = rng().random_range(0..input_len);
-            let input_slice = input_tokens.take(input_range).collect::<Vec<&str>>().join(" ");
-            let random_slice = random_tokens.skip(input_range).collect::<Vec<&str>>().join(" ");
-
-            return Some(input_slice + random_slice.as_str())
-        }
-
-        let random_range = rng().random_range(0..random_len);
-        let random_slice = random_tokens.take(random_range).collect::<Vec<&str>>().join(" ");
-        let input_slice = input_tokens.skip(random_range).collect::<Vec<&str>>().join(" ");
-
-        Some(random_slice + input_slice.as_str())
-    }
-}
-
-pub struct Pronouns<'a> {
-    pub primary: [&'a str; 4],
-    pub other: [&'a str; 4],
-    pub owning: [&'a str; 4],
-    pub all: [&'a str; 12],
-}
-
-// Internals for misgendering mutator
-impl Misgendering {
-    const PRONOUNS: Pronouns<'static> = Pronouns {
-        primary: ["he", "she", "it", "they"],
-        other: ["him", "her", "it", "them"],
-        owning: ["his", "her", "its", "their"],
-        all: [
-            "he", "she", "it", "they", "him", "her", "it", "them", "his", "her", "its", "their",
-        ],
-    };
-
-    fn randomize_pronoun(input: Vec<&str>, idx: usize) -> Vec<&str> {
-        let mut output: Vec<&str> = input.clone();
-        if Self::PRONOUNS.primary.contains(&input[idx]) {
-            output[idx] = Self::PRONOUNS.primary.choose(&mut rng()).unwrap();
-        } else if Self::PRONOUNS.other.contains(&input[idx]) {
-            output[idx] = Self::PRONOUNS.other.choose(&mut rng()).unwrap();
-        } else if Self::PRONOUNS.owning.contains(&input[idx]) {
-            output[idx] = Self::PRONOUNS.owning.choose(&mut rng()).unwrap();
-        }
-        return output;
-    }
-}
-
-#[async_trait] // Swaps around pronouns in the input
-impl MessageMutator for Misgendering {
-    async fn mutate(&self, input: String, _: &Context, _: &SclunerGuild) -> Option<String> {
-        if !rng().random_ratio(1, 9) {
-            return None;
-        }
-
-        let any_contained: bool = input.split_whitespace().any(|e| {
-            Misgendering::PRONOUNS
-                .all
-                .contains(&e.to_lowercase().as_str())
-        });
-
-        if !any_contained {
-            return None;
-        }
-
-        let mut new_tokens: Vec<&str> = input.split_whitespace().collect();
-
-        // Save indexes where woke
-        let mut pronoun_indexes: Vec<usize> = Vec::new();
-
-        for (i, token) in new_tokens.iter().enumerate() {
-            let token_lowercase = token.to_lowercase();
-            let token_lowercase_str = token_lowercase.as_str();
-
-            if Misgendering::PRONOUNS.all.contains(&token_lowercase_str) {
-                pronoun_indexes.push(i);
-            }
-        }
-
-        // Make sure at least one pronoun is woked
-        let guaranteed_idx = pronoun_indexes
-            .swap_remove(pronoun_indexes[rng().random_range(0..pronoun_indexes.len())]);
-        new_tokens = Misgendering::randomize_pronoun(new_tokens, guaranteed_idx);
-
-        // 3/4 chance for each one to :3
-        for to_change_idx in pronoun_indexes {
-            if rng().random_ratio(3, 4) {
-                new_tokens = Misgendering::randomize_pronoun(new_tokens, to_change_idx);
-            }
-        }
-
-        Some(new_tokens.join(" "))
-    }
-}
+use std::sync::{Arc, OnceLock};
+
+use poise::serenity_prelude::*;
+use rand::prelude::IndexedRandom;
+use rand::Rng;
+use regex::{Regex, RegexBuilder};
+use serde::{Serialize, Deserialize};
+
+use crate::{Error, SclunerGuild, SclunerRng};
+use crate::transport::Transport;
+use crate::vocabulary::{is_swappable, normalize};
+
+pub type MutatorRef = Arc<dyn MessageMutator + Send + Sync>;
+
+// Limits for guild made regex mutators
+pub const MAX_REGEX_MUTATORS: usize = 20;
+const MAX_PATTERN_LEN: usize = 200;
+const MAX_REPLACEMENT_LEN: usize = 200;
+// Bytes, keeps compiling and matching cheap
+const MAX_REGEX_SIZE: usize = 1 << 16;
+// Discord's message limit
+const MAX_OUTPUT_LEN: usize = 2000;
+// Same as the longest message that gets remembered
+const MAX_SPLICE_TOKENS: usize = 30;
+
+#[async_trait]
+pub trait MessageMutator: Send + Sync {
+    // Authors of any memories pulled into the output go in sources
+    async fn mutate(&self, input: String, transport: &dyn Transport, guild: &SclunerGuild, rng: &mut SclunerRng, sources: &mut Vec<UserId>) -> Option<String>;
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash, poise::ChoiceParameter)]
+pub enum DefinedMutators {
+    AppendEmote,
+    MessageSplicer,
+    Misgendering,
+    Typos,
+    Uwuify,
+    Stutter,
+    RandomCaps,
+    WordShuffle,
+    WordSwap,
+}
+
+impl DefinedMutators {
+    pub fn all() -> Vec<Self> {
+        vec![
+            Self::AppendEmote,
+            Self::MessageSplicer,
+            Self::Misgendering,
+            Self::Typos,
+            Self::Uwuify,
+            Self::Stutter,
+            Self::RandomCaps,
+            Self::WordShuffle,
+            Self::WordSwap,
+        ]
+    }
+
+    // Case insensitive variant name
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::all().into_iter().find(|m| format!("{:?}", m).eq_ignore_ascii_case(name.trim()))
+    }
+
+    // (chance, out_of) of running on a message unless the guild sets its own
+    pub fn default_chance(&self) -> (u32, u32) {
+        match self {
+            Self::AppendEmote => (1, 16),
+            Self::MessageSplicer => (1, 16),
+            Self::Misgendering => (1, 9),
+            Self::Typos => (1, 16),
+            Self::Uwuify => (1, 32),
+            Self::Stutter => (1, 16),
+            Self::RandomCaps => (1, 32),
+            Self::WordShuffle => (1, 24),
+            Self::WordSwap => (1, 12),
+        }
+    }
+
+    // Newer mutators have to be enabled by the guild
+    pub fn default_allowed() -> Vec<Self> {
+        vec![
+            Self::AppendEmote,
+            Self::MessageSplicer,
+            Self::Misgendering
+        ]
+    }
+
+    pub fn to_mutators(allowed: &[Self]) -> Vec<MutatorRef> {
+        allowed.iter().map(|mutator| {
+            match mutator {
+                DefinedMutators::AppendEmote => Arc::new(AppendEmote) as MutatorRef,
+                DefinedMutators::MessageSplicer => Arc::new(MessageSplicer) as MutatorRef,
+                DefinedMutators::Misgendering => Arc::new(Misgendering) as MutatorRef,
+                DefinedMutators::Typos => Arc::new(Typos) as MutatorRef,
+                DefinedMutators::Uwuify => Arc::new(Uwuify) as MutatorRef,
+                DefinedMutators::Stutter => Arc::new(Stutter) as MutatorRef,
+                DefinedMutators::RandomCaps => Arc::new(RandomCaps) as MutatorRef,
+                DefinedMutators::WordShuffle => Arc::new(WordShuffle) as MutatorRef,
+                DefinedMutators::WordSwap => Arc::new(WordSwap) as MutatorRef,
+            }
+        }).collect()
+    }
+}
+
+// DEFINITIONS
+pub struct AppendEmote;
+pub struct MessageSplicer;
+pub struct Misgendering;
+pub struct Typos;
+pub struct Uwuify;
+pub struct Stutter;
+pub struct RandomCaps;
+pub struct WordShuffle;
+pub struct WordSwap;
+
+// Made by a guild's mods, replaces matches of pattern with replacement ($1, $name work)
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RegexMutator {
+    pub name: String,
+    pub pattern: String,
+    pub replacement: String,
+    pub chance: u32,
+    pub out_of: u32,
+
+    // Shared between clones so one loaded from a backup only compiles once
+    #[serde(skip)]
+    regex: Arc<OnceLock<Regex>>,
+}
+
+impl RegexMutator {
+    // Fails if the pattern doesn't compile or anything is too big
+    pub fn new(name: String, pattern: String, replacement: String, chance: u32, out_of: u32) -> Result<Self, Error> {
+        if out_of == 0 || chance > out_of {
+            return Err("chance must be <= out_of, out_of can't be 0".into());
+        }
+        if replacement.len() > MAX_REPLACEMENT_LEN {
+            return Err(format!("replacement is longer than {} bytes", MAX_REPLACEMENT_LEN).into());
+        }
+
+        let regex = Self::compile(&pattern)?;
+
+        Ok(Self {
+            name,
+            pattern,
+            replacement,
+            chance,
+            out_of,
+            regex: Arc::new(OnceLock::from(regex)),
+        })
+    }
+
+    fn compile(pattern: &str) -> Result<Regex, Error> {
+        if pattern.len() > MAX_PATTERN_LEN {
+            return Err(format!("pattern is longer than {} bytes", MAX_PATTERN_LEN).into());
+        }
+
+        Ok(RegexBuilder::new(pattern)
+            .size_limit(MAX_REGEX_SIZE)
+            .dfa_size_limit(MAX_REGEX_SIZE)
+            .build()?)
+    }
+
+    // None if nothing matched or the result is too long to send
+    pub fn apply(&self, input: &str) -> Option<String> {
+        let regex = match self.regex.get() {
+            Some(r) => r,
+            // Loaded from a backup
+            None => match Self::compile(&self.pattern) {
+                Ok(r) => self.regex.get_or_init(|| r),
+                Err(e) => {
+                    eprintln!("REGEX MUTATOR {} DOESN'T COMPILE: {}", self.name, e);
+                    return None;
+                }
+            },
+        };
+
+        if !regex.is_match(input) {
+            return None;
+        }
+
+        let output = regex.replace_all(input, self.replacement.as_str()).into_owned();
+        if output.len() > MAX_OUTPUT_LEN {
+            return None;
+        }
+
+        Some(output)
+    }
+}
+
+// IMPLEMENTATIONS
+#[async_trait] // Appends an emote at the end of the input
+impl MessageMutator for AppendEmote {
+    async fn mutate(&self, input: String, _: &dyn Transport, guild: &SclunerGuild, rng: &mut SclunerRng, _: &mut Vec<UserId>) -> Option<String> {
+        let emotes = guild.emoji_pool();
+        let emote = emotes.choose(rng)?;
+
+        Some(format!("{} {}", input, emote))
+    }
+}
+
+#[async_trait] // Runs a guild's regex over the input
+impl MessageMutator for RegexMutator {
+    async fn mutate(&self, input: String, _: &dyn Transport, _: &SclunerGuild, _: &mut SclunerRng, _: &mut Vec<UserId>) -> Option<String> {
+        self.apply(&input)
+    }
+}
+
+#[async_trait] // Splices the input and another memory together at a clause, word or shared word
+impl MessageMutator for MessageSplicer {
+    async fn mutate(&self, input: String, _: &dyn Transport, guild: &SclunerGuild, rng: &mut SclunerRng, sources: &mut Vec<UserId>) -> Option<String> {
+        let memory = guild.messages.choose(rng)?;
+
+        let input_tokens: Vec<&str> = input.split_whitespace().collect();
+        let memory_tokens: Vec<&str> = memory.content.split_whitespace().collect();
+
+        if input_tokens.is_empty() || memory_tokens.is_empty() {
+            return None;
+        }
+
+        let (head, tail) = match rng.random_bool(0.5) {
+            true => (&input_tokens, &memory_tokens),
+            false => (&memory_tokens, &input_tokens),
+        };
+
+        let mut spliced = MessageSplicer::splice(head, tail, rng);
+        spliced.truncate(MAX_SPLICE_TOKENS);
+
+        let output = spliced.join(" ");
+        if output.len() > MAX_OUTPUT_LEN || spliced == input_tokens {
+            return None;
+        }
+
+        sources.push(memory.user_id);
+        Some(output)
+    }
+}
+
+// Internals for message splicer
+impl MessageSplicer {
+    fn ends_clause(token: &str) -> bool {
+        token.ends_with([',', ';', ':', '.', '!', '?'])
+    }
+
+    // Where tokens can be cut, preferring after the end of a clause
+    fn cut_points(tokens: &[&str]) -> Vec<usize> {
+        let clauses: Vec<usize> = (1..tokens.len()).filter(|i| Self::ends_clause(tokens[i - 1])).collect();
+
+        match clauses.is_empty() {
+            true => (1..tokens.len()).collect(),
+            false => clauses,
+        }
+    }
+
+    // (head index, tail index) of words both share
+    fn pivots(head: &[&str], tail: &[&str]) -> Vec<(usize, usize)> {
+        let mut pivots = Vec::new();
+
+        for (i, h) in head.iter().enumerate() {
+            let h = normalize(h);
+            if h.is_empty() {
+                continue;
+            }
+
+            for (j, t) in tail.iter().enumerate() {
+                if normalize(t) == h {
+                    pivots.push((i, j));
+                }
+            }
+        }
+
+        pivots
+    }
+
+    // Start of head then end of tail, through a shared word half the time if there is one
+    fn splice<'a>(head: &[&'a str], tail: &[&'a str], rng: &mut SclunerRng) -> Vec<&'a str> {
+        let pivots = Self::pivots(head, tail);
+
+        if let Some((i, j)) = pivots.choose(rng).filter(|_| rng.random_bool(0.5)) {
+            return head[..=*i].iter().chain(&tail[j + 1..]).copied().collect();
+        }
+
+        // Single words get used whole
+        let head_cut = Self::cut_points(head).choose(rng).copied().unwrap_or(head.len());
+        let tail_cut = Self::cut_points(tail).choose(rng).copied().unwrap_or(0);
+
+        head[..head_cut].iter().chain(&tail[tail_cut..]).copied().collect()
+    }
+}
+
+// One way of referring to someone, e.g. they / them / their
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct PronounSet {
+    pub primary: String,
+    pub other: String,
+    pub owning: String,
+}
+
+impl PronounSet {
+    pub fn new(primary: &str, other: &str, owning: &str) -> Self {
+        Self {
+            primary: primary.to_lowercase(),
+            other: other.to_lowercase(),
+            owning: owning.to_lowercase(),
+        }
+    }
+
+    pub fn default_sets() -> Vec<Self> {
+        vec![
+            Self::new("he", "him", "his"),
+            Self::new("she", "her", "her"),
+            Self::new("it", "it", "its"),
+            Self::new("they", "them", "their"),
+        ]
+    }
+
+    fn role(&self, role: usize) -> &str {
+        match role {
+            0 => &self.primary,
+            1 => &self.other,
+            _ => &self.owning,
+        }
+    }
+}
+
+impl std::fmt::Display for PronounSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}/{}", self.primary, self.other, self.owning)
+    }
+}
+
+// Internals for misgendering mutator
+impl Misgendering {
+    // Splits into runs of letters and everything between them, so punctuation stays put
+    fn segments(input: &str) -> Vec<(String, bool)> {
+        let mut segments: Vec<(String, bool)> = Vec::new();
+
+        for c in input.chars() {
+            let is_word = c.is_alphabetic();
+
+            match segments.last_mut() {
+                Some((segment, word)) if *word == is_word => segment.push(c),
+                _ => segments.push((c.to_string(), is_word)),
+            }
+        }
+
+        segments
+    }
+
+    // Which role (primary, other, owning) a word is in any of the sets
+    fn role_of(word: &str, sets: &[PronounSet]) -> Option<usize> {
+        let word = word.to_lowercase();
+
+        (0..3).find(|role| sets.iter().any(|set| set.role(*role) == word))
+    }
+
+    fn randomize_pronoun(word: &str, sets: &[PronounSet], rng: &mut SclunerRng) -> String {
+        let role = match Self::role_of(word, sets) {
+            None => return word.to_string(),
+            Some(r) => r,
+        };
+
+        match sets.choose(rng) {
+            None => word.to_string(),
+            Some(set) => match_case(word, set.role(role)),
+        }
+    }
+}
+
+#[async_trait] // Swaps around pronouns in the input
+impl MessageMutator for Misgendering {
+    async fn mutate(&self, input: String, _: &dyn Transport, guild: &SclunerGuild, rng: &mut SclunerRng, _: &mut Vec<UserId>) -> Option<String> {
+        let sets = &guild.pronoun_sets;
+        let mut segments = Misgendering::segments(&input);
+
+        // Save indexes where woke
+        let mut pronoun_indexes: Vec<usize> = segments
+            .iter()
+            .enumerate()
+            .filter(|(_, (segment, word))| *word && Misgendering::role_of(segment, sets).is_some())
+            .map(|(i, _)| i)
+            .collect();
+
+        if pronoun_indexes.is_empty() {
+            return None;
+        }
+
+        // Make sure at least one pronoun is woked
+        let guaranteed_idx = pronoun_indexes.swap_remove(rng.random_range(0..pronoun_indexes.len()));
+        segments[guaranteed_idx].0 = Misgendering::randomize_pronoun(&segments[guaranteed_idx].0, sets, rng);
+
+        // 3/4 chance for each one to :3
+        for to_change_idx in pronoun_indexes {
+            if rng.random_ratio(3, 4) {
+                segments[to_change_idx].0 = Misgendering::randomize_pronoun(&segments[to_change_idx].0, sets, rng);
+            }
+        }
+
+        let output: String = segments.into_iter().map(|(segment, _)| segment).collect();
+
+        // Every pick landed on the same pronoun
+        if output == input {
+            return None;
+        }
+
+        Some(output)
+    }
+}
+
+// Text helpers for the mutators below
+// Mentions, custom emotes, channels and links shouldn't be messed with
+fn is_protected(word: &str) -> bool {
+    (word.starts_with('<') && word.ends_with('>'))
+        || word.starts_with("http://")
+        || word.starts_with("https://")
+}
+
+// Applies change to every word that isn't protected, keeping the whitespace between them
+fn map_words(input: &str, mut change: impl FnMut(&str) -> String) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut word = String::new();
+
+    let mut flush = |word: &mut String, output: &mut String| {
+        match is_protected(word) {
+            true => output.push_str(word),
+            false => output.push_str(&change(word)),
+        }
+        word.clear();
+    };
+
+    for c in input.chars() {
+        match c.is_whitespace() {
+            true => {
+                flush(&mut word, &mut output);
+                output.push(c);
+            }
+            false => word.push(c),
+        }
+    }
+    flush(&mut word, &mut output);
+
+    output
+}
+
+// Gives replacement the casing of original, HE -> SHE, He -> She, he -> she
+fn match_case(original: &str, replacement: &str) -> String {
+    let mut chars = original.chars();
+    let first_upper = chars.next().is_some_and(|c| c.is_uppercase());
+    let rest_upper = chars.clone().count() > 0 && chars.all(|c| c.is_uppercase());
+
+    if first_upper && rest_upper {
+        return replacement.to_uppercase();
+    }
+
+    let mut replacement_chars = replacement.chars();
+    match (first_upper, replacement_chars.next()) {
+        (true, Some(first)) => first.to_uppercase().chain(replacement_chars).collect(),
+        _ => replacement.to_string(),
+    }
+}
+
+// Keys next to each other on a QWERTY keyboard
+fn adjacent_keys(key: char) -> &'static str {
+    match key {
+        'q' => "wa", 'w' => "qeas", 'e' => "wrsd", 'r' => "etdf", 't' => "ryfg",
+        'y' => "tugh", 'u' => "yihj", 'i' => "uojk", 'o' => "ipkl", 'p' => "ol",
+        'a' => "qwsz", 's' => "awedxz", 'd' => "serfcx", 'f' => "drtgvc", 'g' => "ftyhbv",
+        'h' => "gyujnb", 'j' => "huikmn", 'k' => "jiolm", 'l' => "kop",
+        'z' => "asx", 'x' => "zsdc", 'c' => "xdfv", 'v' => "cfgb", 'b' => "vghn",
+        'n' => "bhjm", 'm' => "njk",
+        _ => "",
+    }
+}
+
+#[async_trait] // Hits a key next to the right one every now and then
+impl MessageMutator for Typos {
+    async fn mutate(&self, input: String, _: &dyn Transport, _: &SclunerGuild, rng: &mut SclunerRng, _: &mut Vec<UserId>) -> Option<String> {
+        let output = map_words(&input, |word| {
+            word.chars()
+                .map(|c| {
+                    let adjacent: Vec<char> = adjacent_keys(c.to_ascii_lowercase()).chars().collect();
+
+                    match adjacent.choose(rng) {
+                        Some(typo) if rng.random_ratio(1, 12) => match c.is_uppercase() {
+                            true => typo.to_ascii_uppercase(),
+                            false => *typo,
+                        },
+                        _ => c,
+                    }
+                })
+                .collect()
+        });
+
+        (output != input).then_some(output)
+    }
+}
+
+#[async_trait] // Talks wike this uwu
+impl MessageMutator for Uwuify {
+    async fn mutate(&self, input: String, _: &dyn Transport, _: &SclunerGuild, rng: &mut SclunerRng, _: &mut Vec<UserId>) -> Option<String> {
+        let mut output = map_words(&input, |word| {
+            let word = word.replace("ove", "uv").replace("OVE", "UV");
+            let mut uwu = String::with_capacity(word.len());
+            let mut chars = word.chars().peekable();
+
+            while let Some(c) = chars.next() {
+                match c {
+                    'r' | 'l' => uwu.push('w'),
+                    'R' | 'L' => uwu.push('W'),
+                    'n' | 'N' if chars.peek().is_some_and(|v| "aeiou".contains(*v)) => {
+                        uwu.push(c);
+                        uwu.push('y');
+                    }
+                    _ => uwu.push(c),
+                }
+            }
+
+            uwu
+        });
+
+        if output == input {
+            return None;
+        }
+
+        if rng.random_ratio(1, 4) {
+            output += [" uwu", " owo", " >w<"].choose(rng)?;
+        }
+
+        (output.len() <= MAX_OUTPUT_LEN).then_some(output)
+    }
+}
+
+#[async_trait] // S-s-stutters on the first word
+impl MessageMutator for Stutter {
+    async fn mutate(&self, input: String, _: &dyn Transport, _: &SclunerGuild, rng: &mut SclunerRng, _: &mut Vec<UserId>) -> Option<String> {
+        let first = input
+            .split_whitespace()
+            .next()
+            .filter(|w| !is_protected(w))?
+            .chars()
+            .next()
+            .filter(|c| c.is_alphabetic())?;
+
+        let start = input.len() - input.trim_start().len();
+        let stutter = format!("{}-", first).repeat(rng.random_range(1..=2));
+        let output = format!("{}{}{}", &input[..start], stutter, &input[start..]);
+
+        (output.len() <= MAX_OUTPUT_LEN).then_some(output)
+    }
+}
+
+#[async_trait] // rAnDoMlY cApItAlIzEs letters
+impl MessageMutator for RandomCaps {
+    async fn mutate(&self, input: String, _: &dyn Transport, _: &SclunerGuild, rng: &mut SclunerRng, _: &mut Vec<UserId>) -> Option<String> {
+        let output = map_words(&input, |word| {
+            word.chars()
+                .map(|c| match rng.random_bool(0.5) {
+                    true => c.to_uppercase().collect::<String>(),
+                    false => c.to_lowercase().collect::<String>(),
+                })
+                .collect()
+        });
+
+        (output != input && output.len() <= MAX_OUTPUT_LEN).then_some(output)
+    }
+}
+
+#[async_trait] // Swaps a few neighbouring words around
+impl MessageMutator for WordShuffle {
+    async fn mutate(&self, input: String, _: &dyn Transport, _: &SclunerGuild, rng: &mut SclunerRng, _: &mut Vec<UserId>) -> Option<String> {
+        let mut tokens: Vec<&str> = input.split_whitespace().collect();
+        if tokens.len() < 2 {
+            return None;
+        }
+
+        let original = tokens.clone();
+
+        // More swaps for longer messages
+        for _ in 0..=tokens.len() / 8 {
+            let i = rng.random_range(0..tokens.len() - 1);
+            tokens.swap(i, i + 1);
+        }
+
+        (tokens != original).then(|| tokens.join(" "))
+    }
+}
+
+#[async_trait] // Swaps words for others the guild uses in the same spots
+impl MessageMutator for WordSwap {
+    async fn mutate(&self, input: String, _: &dyn Transport, guild: &SclunerGuild, rng: &mut SclunerRng, _: &mut Vec<UserId>) -> Option<String> {
+        let vocabulary = guild.vocabulary.as_ref()?;
+
+        let mut tokens: Vec<String> = input.split_whitespace().map(str::to_string).collect();
+        let words: Vec<String> = tokens.iter().map(|t| normalize(t)).collect();
+        let word_at = |i: Option<usize>| i.and_then(|i| words.get(i)).map_or("", String::as_str);
+
+        let swappable: Vec<usize> = (0..tokens.len())
+            .filter(|i| !is_protected(&tokens[*i]) && is_swappable(&words[*i]))
+            .collect();
+
+        // At least one, more in longer messages
+        let swaps = rng.random_range(1..=swappable.len().div_ceil(6).max(1));
+        let mut swapped = false;
+
+        for i in swappable.choose_multiple(rng, swaps).copied().collect::<Vec<_>>() {
+            let candidates = vocabulary.candidates(word_at(i.checked_sub(1)), &words[i], word_at(Some(i + 1)));
+            let Ok((replacement, _)) = candidates.choose_weighted(rng, |(_, count)| *count) else { continue };
+
+            // Keep the punctuation around the word
+            let token = &tokens[i];
+            let punctuation = |c: char| !c.is_alphanumeric();
+            let start = token.len() - token.trim_start_matches(punctuation).len();
+            let end = token.trim_end_matches(punctuation).len();
+
+            tokens[i] = format!("{}{}{}", &token[..start], match_case(&token[start..end], replacement), &token[end..]);
+            swapped = true;
+        }
+
+        swapped.then(|| tokens.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::memory_transport::MemoryTransport;
+    use ciborium::Value;
+
+    const GUILD: GuildId = GuildId::new(3);
+
+    const INPUTS: [&str; 9] = [
+        "",
+        "   ",
+        "a",
+        "he",
+        "He.",
+        "héllo wörld 🐸 ünïcode",
+        "he she it they him her them his its their",
+        "They said she gave him her book and his pen to them",
+        "the quick brown fox jumps over the lazy dog again and again and again",
+    ];
+
+    fn guild(messages: &[&str]) -> SclunerGuild {
+        let mut guild = SclunerGuild::new(GUILD, SclunerRng::seed_from_u64(0));
+        guild.messages = messages
+            .iter()
+            .map(|m| crate::SclunerMessage { user_id: UserId::new(2), content: m.to_string() })
+            .collect();
+        guild.vocabulary = Some(crate::vocabulary::Vocabulary::build(&guild.messages));
+        guild.set_emojis(vec![ReactionType::Unicode("🐸".to_string())]);
+        guild
+    }
+
+    fn mutate(mutator: &dyn MessageMutator, input: &str, guild: &SclunerGuild, seed: u64) -> Option<String> {
+        let mut rng = SclunerRng::seed_from_u64(seed);
+        async_std::task::block_on(mutator.mutate(input.to_string(), &MemoryTransport::new(UserId::new(1)), guild, &mut rng, &mut Vec::new()))
+    }
+
+    #[test]
+    fn no_input_panics() {
+        let guilds = [guild(&[]), guild(&[""]), guild(&INPUTS)];
+
+        for guild in &guilds {
+            for mutator in DefinedMutators::to_mutators(&DefinedMutators::all()) {
+                for input in INPUTS {
+                    for seed in 0..50 {
+                        mutate(mutator.as_ref(), input, guild, seed);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn append_emote_keeps_input() {
+        let mut guild = guild(&[]);
+
+        for input in INPUTS {
+            assert_eq!(mutate(&AppendEmote, input, &guild, 0), Some(format!("{} 🐸", input)));
+        }
+
+        guild.set_emojis(Vec::new());
+        assert_eq!(mutate(&AppendEmote, "hi", &guild, 0), None);
+
+        guild.unicode_emojis = true;
+        let output = mutate(&AppendEmote, "hi", &guild, 0).unwrap();
+        assert!(crate::emoji_cache::UNICODE_EMOJIS.iter().any(|e| output == format!("hi {}", e)));
+    }
+
+    #[test]
+    fn splicer_only_uses_known_tokens() {
+        let guild = guild(&["one two three four five six", "ünïcode 🐸"]);
+
+        for input in INPUTS {
+            for seed in 0..50 {
+                let output = match mutate(&MessageSplicer, input, &guild, seed) {
+                    None => {
+                        assert!(input.trim().is_empty());
+                        continue;
+                    }
+                    Some(o) => o,
+                };
+
+                assert!(!output.is_empty());
+                for token in output.split_whitespace() {
+                    assert!(input.split_whitespace().chain(guild.messages.iter().flat_map(|m| m.content.split_whitespace())).any(|t| t == token));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn splicer_cuts_at_clauses_and_pivots() {
+        let clauses = guild(&["well, that was fun. anyway"]);
+        for seed in 0..50 {
+            let output = mutate(&MessageSplicer, "i went home, then i slept", &clauses, seed).unwrap();
+            assert!([
+                "well, then i slept",
+                "well, that was fun. then i slept",
+                "i went home, that was fun. anyway",
+                "i went home, anyway",
+            ].contains(&output.as_str()), "{}", output);
+        }
+
+        let pivots = guild(&["my dog likes green grass"]);
+        let outputs: Vec<String> = (0..50).filter_map(|seed| mutate(&MessageSplicer, "i like green cats", &pivots, seed)).collect();
+        assert!(outputs.iter().any(|o| o == "i like green grass" || o == "my dog likes green cats"));
+
+        let long = ["word"; 40].join(" ");
+        let other = ["other"; 40].join(" ");
+        for seed in 0..50 {
+            let output = mutate(&MessageSplicer, &long, &guild(&[&other]), seed).unwrap();
+            assert!(output.split_whitespace().count() <= MAX_SPLICE_TOKENS);
+        }
+    }
+
+    #[test]
+    fn splicer_reports_its_source() {
+        let mut guild = guild(&["the other message"]);
+        guild.messages[0].user_id = UserId::new(7);
+
+        let mut sources = Vec::new();
+        let mut rng = SclunerRng::seed_from_u64(0);
+        let output = async_std::task::block_on(MessageSplicer.mutate("my own words".to_string(), &MemoryTransport::new(UserId::new(1)), &guild, &mut rng, &mut sources));
+
+        assert!(output.is_some());
+        assert_eq!(sources, [UserId::new(7)]);
+    }
+
+    #[test]
+    fn misgendering_only_changes_pronouns() {
+        let guild = guild(&[]);
+
+        for input in INPUTS {
+            for seed in 0..50 {
+                let output = match mutate(&Misgendering, input, &guild, seed) {
+                    None => continue,
+                    Some(o) => o,
+                };
+
+                let before = Misgendering::segments(input);
+                let after = Misgendering::segments(&output);
+
+                assert_eq!(before.len(), after.len());
+                assert_ne!(before, after);
+                for ((b, word), (a, _)) in before.iter().zip(&after) {
+                    let sets = &guild.pronoun_sets;
+                    assert!(b == a || *word && Misgendering::role_of(b, sets).is_some_and(|role| sets.iter().any(|s| s.role(role) == a.to_lowercase())));
+                }
+            }
+        }
+
+        assert_eq!(mutate(&Misgendering, "no pronouns here", &guild, 0), None);
+    }
+
+    #[test]
+    fn misgendering_keeps_case_and_punctuation() {
+        let mut guild = guild(&[]);
+        guild.pronoun_sets = vec![PronounSet::new("he", "him", "his"), PronounSet::new("she", "her", "her")];
+
+        // Every output a mutation can give
+        let outputs = |input: &str, guild: &SclunerGuild| -> Vec<String> {
+            let mut outputs: Vec<String> = (0..50).filter_map(|seed| mutate(&Misgendering, input, guild, seed)).collect();
+            outputs.sort();
+            outputs.dedup();
+            outputs
+        };
+
+        assert_eq!(outputs("He.", &guild), ["She."]);
+        assert_eq!(outputs("tell him, ok?", &guild), ["tell her, ok?"]);
+        assert_eq!(outputs("THAT'S HIS!!", &guild), ["THAT'S HER!!"]);
+        assert_eq!(outputs("  he's   (him)  ", &guild), ["  he's   (her)  ", "  she's   (her)  ", "  she's   (him)  "]);
+
+        guild.pronoun_sets = vec![PronounSet::new("they", "them", "their"), PronounSet::new("xe", "xem", "xyr")];
+        assert_eq!(outputs("Xe lost xyr keys", &guild), ["They lost their keys", "They lost xyr keys", "Xe lost their keys"]);
+        assert!(outputs("he lost his keys", &guild).is_empty());
+    }
+
+    #[test]
+    fn text_mutators_leave_mentions_and_links_alone() {
+        let guild = guild(&[]);
+        let input = "Hello there <@123> i really love the rain https://x.com/a";
+        let text: [&dyn MessageMutator; 4] = [&Typos, &Uwuify, &Stutter, &RandomCaps];
+
+        for mutator in text {
+            for seed in 0..50 {
+                let output = match mutate(mutator, input, &guild, seed) {
+                    None => continue,
+                    Some(o) => o,
+                };
+
+                assert!(output.contains(" <@123> "));
+                assert!(output.contains(" https://x.com/a"));
+            }
+        }
+
+        assert_eq!(mutate(&Stutter, "<@123> hi", &guild, 0), None);
+        assert_eq!(mutate(&Uwuify, "<:rolled:123>", &guild, 0), None);
+    }
+
+    #[test]
+    fn typos_use_neighbouring_keys() {
+        let guild = guild(&[]);
+        let input = "Hello there i really love the rain";
+
+        for seed in 0..50 {
+            let output = match mutate(&Typos, input, &guild, seed) {
+                None => continue,
+                Some(o) => o,
+            };
+
+            assert_eq!(output.len(), input.len());
+            for (before, after) in input.chars().zip(output.chars()) {
+                let lowercase = before.to_ascii_lowercase();
+                assert!(before == after || adjacent_keys(lowercase).contains(after.to_ascii_lowercase()));
+                assert_eq!(before.is_uppercase(), after.is_uppercase());
+            }
+        }
+
+        assert_eq!(mutate(&Typos, "Hello there", &guild, 0).unwrap(), "Hdllo therd");
+    }
+
+    #[test]
+    fn word_shuffle_keeps_words() {
+        let guild = guild(&[]);
+        let input = "one two three four five six seven eight nine ten";
+
+        for seed in 0..50 {
+            let output = match mutate(&WordShuffle, input, &guild, seed) {
+                // Swapped back and forth
+                None => continue,
+                Some(o) => o,
+            };
+
+            let mut before: Vec<&str> = input.split_whitespace().collect();
+            let mut after: Vec<&str> = output.split_whitespace().collect();
+
+            assert_ne!(before, after);
+            before.sort();
+            after.sort();
+            assert_eq!(before, after);
+        }
+
+        assert_eq!(mutate(&WordShuffle, "alone", &guild, 0), None);
+        assert_eq!(mutate(&WordShuffle, "same same", &guild, 0), None);
+    }
+
+    #[test]
+    fn word_swap_uses_the_guilds_words() {
+        let guild = guild(&["i love my cat", "i love my dog", "my dog ate the homework", "feed the cat"]);
+
+        for seed in 0..50 {
+            assert_eq!(mutate(&WordSwap, "my DOG?!", &guild, seed).unwrap(), "my CAT?!");
+        }
+
+        // Whatever came before "my"
+        for seed in 0..50 {
+            let output = mutate(&WordSwap, "Hug my dog", &guild, seed).unwrap();
+            assert!(["Love my dog", "Hug my cat", "Love my cat"].contains(&output.as_str()), "{}", output);
+        }
+
+        let outputs: Vec<String> = (0..50).filter_map(|seed| mutate(&WordSwap, "<@123> the homework", &guild, seed)).collect();
+        assert!(outputs.iter().all(|o| o == "<@123> the cat"));
+        assert!(!outputs.is_empty());
+
+        assert_eq!(mutate(&WordSwap, "unknown words here", &guild, 0), None);
+    }
+
+    #[test]
+    fn text_mutator_golden_outputs() {
+        let guild = guild(&[]);
+        let input = "Hello there <@123> i really love the rain";
+
+        assert_eq!(mutate(&Uwuify, input, &guild, 0).unwrap(), "Hewwo thewe <@123> i weawwy wuv the wain");
+        assert_eq!(mutate(&Uwuify, "no one knows", &guild, 0).unwrap(), "nyo onye knyows");
+        assert_eq!(mutate(&Stutter, input, &guild, 2).unwrap(), "H-Hello there <@123> i really love the rain");
+        assert_eq!(mutate(&Stutter, "  hi", &guild, 0).unwrap(), "  h-h-hi");
+        assert_eq!(mutate(&RandomCaps, "hello", &guild, 0).unwrap(), "heLlO");
+        assert_eq!(mutate(&WordShuffle, "one two three", &guild, 0).unwrap(), "one three two");
+    }
+
+    #[test]
+    fn golden_outputs() {
+        let guild = guild(&["one two three four five six"]);
+        let input = "They said she gave him her book";
+
+        assert_eq!(mutate(&MessageSplicer, input, &guild, 0).unwrap(), "one two three four her book");
+        assert_eq!(mutate(&MessageSplicer, input, &guild, 4).unwrap(), "They said she gave five six");
+        assert_eq!(mutate(&Misgendering, input, &guild, 0).unwrap(), "They said it gave him it book");
+        assert_eq!(mutate(&Misgendering, input, &guild, 1).unwrap(), "He said he gave it them book");
+    }
+
+    #[test]
+    fn loaded_regex_mutators_compile_once() {
+        let mutator = RegexMutator::new("a".to_string(), "a+".to_string(), "b".to_string(), 1, 1).unwrap();
+        let loaded: RegexMutator = Value::serialized(&mutator).unwrap().deserialized().unwrap();
+        assert!(loaded.regex.get().is_none());
+
+        assert_eq!(loaded.clone().apply("caaat"), Some("cbt".to_string()));
+        assert!(loaded.regex.get().is_some());
+    }
+
+    #[test]
+    fn random_caps_stays_sendable() {
+        // Uppercase ΐ is three chars and six bytes
+        let input = "ΐ".repeat(900);
+
+        for seed in 0..20 {
+            assert_eq!(mutate(&RandomCaps, &input, &guild(&[]), seed), None);
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::HashMap;
+
+use poise::serenity_prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::emoji_cache::UNICODE_EMOJIS;
+use crate::vocabulary::{is_swappable, normalize};
+
+// Past this, only words already tracked keep counting
+const MAX_TRACKED_WORDS: usize = 5000;
+// How much more being used on the same words counts than being used at all
+const WORD_WEIGHT: u32 = 4;
+
+// How often a guild uses each emoji, in reactions and in messages
+// Custom emojis are keyed by id since they can get renamed, standard ones by themselves
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub struct ReactionStats {
+    pub totals: HashMap<String, u32>,
+    // word -> emojis used on messages with it
+    pub by_word: HashMap<String, HashMap<String, u32>>,
+}
+
+pub fn emoji_key(emoji: &ReactionType) -> String {
+    match emoji {
+        ReactionType::Custom { id, .. } => id.to_string(),
+        ReactionType::Unicode(e) => e.clone(),
+        _ => String::new(),
+    }
+}
+
+// Custom emojis written as <:name:id> and the standard emojis from the list
+pub fn emojis_in(content: &str) -> Vec<ReactionType> {
+    let mut emojis = Vec::new();
+
+    let mut rest = content;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start..];
+        let end = match rest.find('>') {
+            None => break,
+            Some(e) => e,
+        };
+
+        match utils::parse_emoji(&rest[..=end]) {
+            Some(e) => {
+                emojis.push(ReactionType::Custom { animated: e.animated, id: e.id, name: Some(e.name) });
+                rest = &rest[end + 1..];
+            }
+            None => rest = &rest[1..],
+        }
+    }
+
+    for emoji in UNICODE_EMOJIS {
+        for _ in content.matches(emoji) {
+            emojis.push(ReactionType::Unicode(emoji.to_string()));
+        }
+    }
+
+    emojis
+}
+
+fn words(content: &str) -> Vec<String> {
+    let mut words: Vec<String> = content.split_whitespace().map(normalize).filter(|w| is_swappable(w)).collect();
+    words.sort();
+    words.dedup();
+    words
+}
+
+impl ReactionStats {
+    // Someone reacted with emoji to a message saying content
+    pub fn record(&mut self, emoji: &ReactionType, content: &str) {
+        let key = emoji_key(emoji);
+        if key.is_empty() {
+            return;
+        }
+
+        let total = self.totals.entry(key.clone()).or_insert(0);
+        *total = total.saturating_add(1);
+
+        for word in words(content) {
+            if !self.by_word.contains_key(&word) && self.by_word.len() >= MAX_TRACKED_WORDS {
+                continue;
+            }
+
+            let count = self.by_word.entry(word).or_default().entry(key.clone()).or_insert(0);
+            *count = count.saturating_add(1);
+        }
+    }
+
+    // Counts the emojis a message uses as if they were reactions to the rest of it
+    pub fn record_text(&mut self, content: &str) {
+        for emoji in emojis_in(content) {
+            self.record(&emoji, content);
+        }
+    }
+
+    // Whether anything was ever reacted to a message with these words
+    pub fn knows_words(&self, content: &str) -> bool {
+        words(content).iter().any(|w| self.by_word.contains_key(w))
+    }
+
+    // Never 0 so emojis nobody has used yet still come up
+    pub fn weight(&self, emoji: &ReactionType, content: &str) -> u32 {
+        let key = emoji_key(emoji);
+        let total = self.totals.get(&key).copied().unwrap_or(0);
+
+        let on_words: u32 = words(content)
+            .iter()
+            .filter_map(|w| self.by_word.get(w)?.get(&key))
+            .fold(0, |sum, c| sum.saturating_add(*c));
+
+        1u32.saturating_add(total).saturating_add(on_words.saturating_mul(WORD_WEIGHT))
+    }
+
+    // Standard emojis the guild has used, they work in any guild unlike custom ones
+    pub fn used_unicode(&self) -> Vec<ReactionType> {
+        let mut used: Vec<ReactionType> = self
+            .totals
+            .keys()
+            .filter(|k| k.parse::<u64>().is_err())
+            .map(|k| ReactionType::Unicode(k.clone()))
+            .collect();
+        used.sort_by_key(|e| e.to_string());
+        used
+    }
+
+    // Most used first
+    pub fn top(&self, count: usize) -> Vec<(&str, u32)> {
+        let mut top: Vec<(&str, u32)> = self.totals.iter().map(|(k, c)| (k.as_str(), *c)).collect();
+        top.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        top.truncate(count);
+        top
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_emojis_in_text() {
+        let emojis = emojis_in("<:frog:123> lol😂 <a:spin:456><3 <:broken:>");
+
+        assert_eq!(emojis, [
+            ReactionType::Custom { animated: false, id: EmojiId::new(123), name: Some("frog".to_string()) },
+            ReactionType::Custom { animated: true, id: EmojiId::new(456), name: Some("spin".to_string()) },
+            ReactionType::Unicode("😂".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn weighs_emojis_by_use() {
+        let frog = ReactionType::Unicode("🐸".to_string());
+        let duck = ReactionType::Unicode("🦆".to_string());
+        let mut stats = ReactionStats::default();
+
+        stats.record(&frog, "look at this pond");
+        stats.record(&frog, "green things");
+        stats.record_text("quack quack 🦆");
+
+        assert_eq!(stats.weight(&frog, "hello"), 3);
+        assert_eq!(stats.weight(&frog, "what a nice pond"), 3 + WORD_WEIGHT);
+        assert_eq!(stats.weight(&duck, "QUACK!"), 2 + WORD_WEIGHT);
+        assert!(stats.knows_words("pond"));
+        assert!(!stats.knows_words("ocean"));
+        assert_eq!(stats.used_unicode(), [frog, duck]);
+    }
+}
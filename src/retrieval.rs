@@ -0,0 +1,117 @@
+use std::collections::{HashMap, HashSet};
+
+use rand::prelude::IndexedRandom;
+use rand::Rng;
+
+use crate::SclunerMessage;
+
+// How many of the best matching memories get a chance to be picked
+const TOP_MATCHES: usize = 5;
+
+// Lowercased words without punctuation
+pub fn tokenize(content: &str) -> Vec<String> {
+    content
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+// Picks a memory that shares rare words with the trigger, TF-IDF style
+// Falls back to any memory if nothing matches
+pub fn pick_related<'a>(trigger: &str, messages: &'a [SclunerMessage], rng: &mut impl Rng) -> Option<&'a SclunerMessage> {
+    let trigger_tokens: HashSet<String> = tokenize(trigger).into_iter().collect();
+
+    let message_tokens: Vec<HashSet<String>> = messages
+        .iter()
+        .map(|m| tokenize(&m.content).into_iter().collect())
+        .collect();
+
+    let mut document_frequency: HashMap<&str, u32> = HashMap::new();
+    for tokens in &message_tokens {
+        for token in tokens.iter().filter(|t| trigger_tokens.contains(*t)) {
+            *document_frequency.entry(token.as_str()).or_insert(0) += 1;
+        }
+    }
+
+    let total = messages.len() as f64;
+    let mut scored: Vec<(usize, f64)> = message_tokens
+        .iter()
+        .enumerate()
+        .filter_map(|(i, tokens)| {
+            let score: f64 = tokens
+                .iter()
+                .filter_map(|t| document_frequency.get(t.as_str()))
+                // Words in every memory score 0, they say nothing about which one fits
+                .map(|df| (total / *df as f64).ln())
+                .sum();
+
+            // Don't let long memories win just by having more words
+            (score > 0.0).then(|| (i, score / (tokens.len() as f64).sqrt()))
+        })
+        .collect();
+
+    if scored.is_empty() {
+        return messages.choose(rng);
+    }
+
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scored.truncate(TOP_MATCHES);
+
+    scored
+        .choose_weighted(rng, |(_, score)| *score)
+        .ok()
+        .map(|(i, _)| &messages[*i])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SclunerRng;
+    use rand::SeedableRng;
+
+    fn messages(contents: &[&str]) -> Vec<SclunerMessage> {
+        contents
+            .iter()
+            .map(|c| SclunerMessage { user_id: 1.into(), content: c.to_string() })
+            .collect()
+    }
+
+    #[test]
+    fn rare_words_win() {
+        let messages = messages(&["the cat is here", "the dog is here", "the bird is here", "the end"]);
+
+        let mut rng = SclunerRng::seed_from_u64(0);
+
+        // "is" still counts a little, "the" not at all
+        let cats = (0..200)
+            .filter_map(|_| pick_related("where is the cat?", &messages, &mut rng))
+            .filter(|m| m.content == "the cat is here")
+            .count();
+        assert!(cats > 100, "{}", cats);
+
+        for _ in 0..20 {
+            assert_eq!(pick_related("cat", &messages, &mut rng).unwrap().content, "the cat is here");
+        }
+    }
+
+    #[test]
+    fn falls_back_to_any_memory() {
+        let messages = messages(&["the cat", "the dog", "the bird"]);
+        let mut rng = SclunerRng::seed_from_u64(0);
+
+        // "the" is in every memory so it doesn't count as a match
+        let picked: HashSet<&str> = (0..50)
+            .filter_map(|_| pick_related("the fish", &messages, &mut rng))
+            .map(|m| m.content.as_str())
+            .collect();
+        assert_eq!(picked.len(), 3);
+
+        assert!(pick_related("", &messages, &mut rng).is_some());
+    }
+
+    #[test]
+    fn no_memories_no_pick() {
+        assert!(pick_related("hello", &[], &mut SclunerRng::seed_from_u64(0)).is_none());
+    }
+}
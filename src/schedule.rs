@@ -0,0 +1,131 @@
+use poise::serenity_prelude::*;
+use serde::{Deserialize, Serialize};
+
+const MINUTES_PER_DAY: i64 = 24 * 60;
+// Longest a nap can be, anything longer should be sleep
+pub const MAX_NAP_SECS: i64 = 4 * 7 * 86400;
+
+// A daily window where the bot sleeps, in minutes since midnight of the guild's time
+// Windows where end is before start go past midnight
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct QuietHours {
+    pub start: u32,
+    pub end: u32,
+}
+
+// Sleeping until a set time, then saying so in the channel it was started in
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub struct Nap {
+    // Unix timestamp
+    pub until: i64,
+    pub channel_id: ChannelId,
+}
+
+impl QuietHours {
+    // utc_offset is in minutes
+    pub fn contains(&self, utc_offset: i32, now: i64) -> bool {
+        let minute = (now / 60 + utc_offset as i64).rem_euclid(MINUTES_PER_DAY) as u32;
+
+        match self.start <= self.end {
+            true => self.start <= minute && minute < self.end,
+            false => minute >= self.start || minute < self.end,
+        }
+    }
+}
+
+impl std::fmt::Display for QuietHours {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:02}:{:02} - {:02}:{:02}", self.start / 60, self.start % 60, self.end / 60, self.end % 60)
+    }
+}
+
+// "22:30" or "7" into minutes since midnight
+pub fn parse_clock(input: &str) -> Option<u32> {
+    let (hours, minutes) = input.trim().split_once(':').unwrap_or((input.trim(), "0"));
+    let (hours, minutes) = (hours.parse::<u32>().ok()?, minutes.parse::<u32>().ok()?);
+
+    if hours >= 24 || minutes >= 60 {
+        return None;
+    }
+
+    Some(hours * 60 + minutes)
+}
+
+// "+5:30", "-3" or "2" into minutes
+pub fn parse_utc_offset(input: &str) -> Option<i32> {
+    let input = input.trim();
+    let (sign, rest) = match input.strip_prefix('-') {
+        Some(rest) => (-1, rest),
+        None => (1, input.strip_prefix('+').unwrap_or(input)),
+    };
+
+    let minutes = parse_clock(rest)? as i32;
+    if minutes > 14 * 60 {
+        return None;
+    }
+
+    Some(sign * minutes)
+}
+
+// "2h", "45m" or "1h30m" into seconds
+pub fn parse_duration(input: &str) -> Option<i64> {
+    let mut total = 0;
+    let mut number = String::new();
+
+    for c in input.trim().chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'd' | 'h' | 'm' | 's' if !number.is_empty() => {
+                let unit = match c {
+                    'd' => 86400,
+                    'h' => 3600,
+                    'm' => 60,
+                    _ => 1,
+                };
+                total = number.parse::<i64>().ok()?.checked_mul(unit)?.checked_add(total)?;
+                number.clear();
+            }
+            _ => return None,
+        }
+    }
+
+    match number.is_empty() && total > 0 {
+        true => Some(total),
+        false => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quiet_hours_wrap_past_midnight() {
+        let night = QuietHours { start: 22 * 60, end: 7 * 60 };
+
+        assert!(night.contains(0, 23 * 3600));
+        assert!(night.contains(0, 3 * 3600));
+        assert!(!night.contains(0, 12 * 3600));
+        // 20:00 UTC is 23:00 at +3
+        assert!(night.contains(180, 20 * 3600));
+        assert!(!night.contains(-180, 20 * 3600));
+    }
+
+    #[test]
+    fn parses_times() {
+        assert_eq!(parse_clock("22:30"), Some(22 * 60 + 30));
+        assert_eq!(parse_clock("7"), Some(7 * 60));
+        assert_eq!(parse_clock("24:00"), None);
+
+        assert_eq!(parse_utc_offset("+5:30"), Some(330));
+        assert_eq!(parse_utc_offset("-3"), Some(-180));
+        assert_eq!(parse_utc_offset("15"), None);
+
+        assert_eq!(parse_duration("2h"), Some(7200));
+        assert_eq!(parse_duration("1h30m"), Some(5400));
+        assert_eq!(parse_duration("30"), None);
+        assert_eq!(parse_duration("h"), None);
+        assert_eq!(parse_duration("999999999999999d"), None);
+        assert_eq!(parse_duration("99999999999999999999s"), None);
+    }
+}
@@ -1,83 +1,430 @@
-use std::collections::HashMap;
-use poise::serenity_prelude::*;
-use serde::{Deserialize, Serialize};
-use crate::{SclunerGuild, SclunerMessage};
-use crate::mutators::DefinedMutators;
-
-#[derive(Serialize, Deserialize)]
-pub struct SclunerBackup {
-    pub guilds_keys: Vec<GuildId>,
-    pub guilds_values: Vec<SclunerGuild>,
-    pub whitelist: Vec<UserId>,
-    pub blacklist: Vec<UserId>,
-    pub modlist: Vec<UserId>
-}
-
-impl SclunerBackup {
-    pub fn new(guilds: &HashMap<GuildId, SclunerGuild>, whitelist: &Vec<UserId>, blacklist: &Vec<UserId>, modlist: &Vec<UserId>) -> Self {
-        Self {
-            guilds_keys: guilds.keys().cloned().collect(),
-            guilds_values: guilds.values().cloned().collect(),
-            whitelist: whitelist.clone(),
-            blacklist: blacklist.clone(),
-            modlist: modlist.clone(),
-        }
-    }
-}
-
-// BACKUP COMPAT
-// Update these every time SclunerGuild or SclunerBackup changes
-// SclunerMessage is not expected to change
-// 2.0.0 -> 3.0.0
-
-#[derive(Deserialize, Clone)]
-pub struct SclunerGuildCompat {
-    guild_id: GuildId,
-    messages: Vec<SclunerMessage>,
-    asleep: bool,
-
-    min_proc: u32,
-    max_proc: u32,
-    proc_out_of: u32,
-
-    proc: u32,
-}
-
-impl SclunerGuildCompat {
-    pub fn modernise(self) -> SclunerGuild {
-        SclunerGuild{
-            guild_id: self.guild_id,
-            messages: self.messages,
-            asleep: self.asleep,
-
-            allowed_mutators: DefinedMutators::default_allowed(),
-
-            min_proc: self.min_proc,
-            max_proc: self.max_proc,
-            proc_out_of: self.proc_out_of,
-
-            proc: self.proc,
-        }
-    }
-}
-
-#[derive(Deserialize)]
-pub struct SclunerBackupCompat {
-    pub guilds_keys: Vec<GuildId>,
-    pub guilds_values: Vec<SclunerGuildCompat>,
-    pub whitelist: Vec<UserId>,
-    pub blacklist: Vec<UserId>,
-    pub modlist: Vec<UserId>
-}
-
-impl SclunerBackupCompat {
-    pub fn modernise(self) -> SclunerBackup {
-        SclunerBackup{
-            guilds_keys: self.guilds_keys,
-            guilds_values: self.guilds_values.iter().map(move |g| { g.clone().modernise() }).collect(),
-            whitelist: self.whitelist,
-            blacklist: self.blacklist,
-            modlist: self.modlist,
-        }
-    }
-}
\ No newline at end of file
+use std::collections::HashMap;
+use ciborium::Value;
+use poise::serenity_prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::{Error, ProcReroll, ReplyMode, SclunerChannel, SclunerGuild};
+use crate::activity::AdaptiveRate;
+use crate::mutators::{DefinedMutators, PronounSet, RegexMutator};
+use crate::reaction_stats::ReactionStats;
+use crate::schedule::{Nap, QuietHours};
+
+// Bump this and add a migration below every time SclunerGuild or SclunerBackup changes
+// SclunerMessage is not expected to change
+pub const BACKUP_VERSION: u32 = 16;
+
+#[derive(Serialize, Deserialize)]
+pub struct SclunerBackup {
+    pub guilds_keys: Vec<GuildId>,
+    pub guilds_values: Vec<SclunerGuild>,
+    pub whitelist: Vec<UserId>,
+    pub blacklist: Vec<UserId>,
+}
+
+// What actually gets written, the backup itself is kept untyped until it's migrated
+#[derive(Serialize, Deserialize)]
+struct SclunerBackupEnvelope {
+    version: u32,
+    backup: Value,
+}
+
+impl SclunerBackup {
+    pub fn new(guilds: &HashMap<GuildId, SclunerGuild>, whitelist: &[UserId], blacklist: &[UserId]) -> Self {
+        Self {
+            guilds_keys: guilds.keys().cloned().collect(),
+            guilds_values: guilds.values().cloned().collect(),
+            whitelist: whitelist.to_vec(),
+            blacklist: blacklist.to_vec(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let envelope = SclunerBackupEnvelope {
+            version: BACKUP_VERSION,
+            backup: Value::serialized(self)?,
+        };
+
+        let mut data: Vec<u8> = Vec::new();
+        ciborium::into_writer(&envelope, &mut data)?;
+
+        Ok(data)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let raw = ciborium::from_reader::<Value, &[u8]>(bytes)?;
+
+        let (mut version, mut backup) = match raw.deserialized::<SclunerBackupEnvelope>() {
+            Ok(envelope) => (envelope.version, envelope.backup),
+            // Backups from before the envelope existed
+            Err(_) => (legacy_version(&raw), raw),
+        };
+
+        if version > BACKUP_VERSION {
+            return Err(format!("backup version {} is newer than the supported version {}", version, BACKUP_VERSION).into());
+        }
+        if version < FIRST_VERSION {
+            return Err(format!("backup version {} is older than the oldest supported version {}", version, FIRST_VERSION).into());
+        }
+
+        while version < BACKUP_VERSION {
+            let migrate = MIGRATIONS[(version - FIRST_VERSION) as usize];
+
+            if let Err(e) = migrate(&mut backup) {
+                return Err(format!("backup migration {} -> {} failed: {}", version, version + 1, e).into());
+            }
+
+            version += 1;
+        }
+
+        match backup.deserialized::<SclunerBackup>() {
+            Ok(b) => Ok(b),
+            Err(e) => Err(format!("backup version {} couldn't be read: {}", version, e).into()),
+        }
+    }
+}
+
+// MIGRATIONS
+// MIGRATIONS[n] takes a backup from version FIRST_VERSION + n to the next one
+type Migration = fn(&mut Value) -> Result<(), Error>;
+
+const FIRST_VERSION: u32 = 2;
+const MIGRATIONS: &[Migration] = &[
+    v2_to_v3,
+    v3_to_v4,
+    v4_to_v5,
+    v5_to_v6,
+    v6_to_v7,
+    v7_to_v8,
+    v8_to_v9,
+    v9_to_v10,
+    v10_to_v11,
+    v11_to_v12,
+    v12_to_v13,
+    v13_to_v14,
+    v14_to_v15,
+    v15_to_v16,
+];
+
+const _: () = assert!(FIRST_VERSION + MIGRATIONS.len() as u32 == BACKUP_VERSION);
+
+// 2.0.0 -> 3.0.0
+fn v2_to_v3(backup: &mut Value) -> Result<(), Error> {
+    for guild in guilds_mut(backup)? {
+        set_field(guild, "allowed_mutators", DefinedMutators::default_allowed())?;
+    }
+
+    Ok(())
+}
+
+// Reply modes
+fn v3_to_v4(backup: &mut Value) -> Result<(), Error> {
+    for guild in guilds_mut(backup)? {
+        set_field(guild, "reply_mode", ReplyMode::Replay)?;
+        set_field(guild, "markov_order", 2)?;
+        set_field(guild, "markov_max_len", 30)?;
+    }
+
+    Ok(())
+}
+
+// Per guild moderators, everyone on the old global list moderates every guild
+fn v4_to_v5(backup: &mut Value) -> Result<(), Error> {
+    let modlist = remove_field(backup, "modlist").unwrap_or(Value::Array(Vec::new()));
+
+    for guild in guilds_mut(backup)? {
+        set_field(guild, "moderators", &modlist)?;
+        set_field(guild, "mod_role", None::<RoleId>)?;
+        set_field(guild, "manage_guild_mods", false)?;
+    }
+
+    Ok(())
+}
+
+// Per guild blacklists
+fn v5_to_v6(backup: &mut Value) -> Result<(), Error> {
+    for guild in guilds_mut(backup)? {
+        set_field(guild, "blacklist", Vec::<UserId>::new())?;
+    }
+
+    Ok(())
+}
+
+// Proc rerolling
+fn v6_to_v7(backup: &mut Value) -> Result<(), Error> {
+    for guild in guilds_mut(backup)? {
+        set_field(guild, "proc_reroll", ProcReroll::AfterReply)?;
+        set_field(guild, "proc_rolled_at", 0)?;
+    }
+
+    Ok(())
+}
+
+// Adaptive reply rates
+fn v7_to_v8(backup: &mut Value) -> Result<(), Error> {
+    for guild in guilds_mut(backup)? {
+        set_field(guild, "adaptive_rate", None::<AdaptiveRate>)?;
+    }
+
+    Ok(())
+}
+
+// Per channel settings
+fn v8_to_v9(backup: &mut Value) -> Result<(), Error> {
+    for guild in guilds_mut(backup)? {
+        set_field(guild, "channels", HashMap::<ChannelId, SclunerChannel>::new())?;
+        set_field(guild, "learn_by_default", true)?;
+        set_field(guild, "speak_by_default", true)?;
+    }
+
+    Ok(())
+}
+
+// Quiet hours and naps
+fn v9_to_v10(backup: &mut Value) -> Result<(), Error> {
+    for guild in guilds_mut(backup)? {
+        set_field(guild, "utc_offset", 0)?;
+        set_field(guild, "quiet_hours", Vec::<QuietHours>::new())?;
+        set_field(guild, "nap", None::<Nap>)?;
+    }
+
+    Ok(())
+}
+
+// Mutator chances, order and limit
+fn v10_to_v11(backup: &mut Value) -> Result<(), Error> {
+    for guild in guilds_mut(backup)? {
+        set_field(guild, "mutator_chances", HashMap::<DefinedMutators, (u32, u32)>::new())?;
+        set_field(guild, "mutator_order", None::<Vec<DefinedMutators>>)?;
+        set_field(guild, "max_mutators", None::<usize>)?;
+    }
+
+    Ok(())
+}
+
+// Guild made regex mutators
+fn v11_to_v12(backup: &mut Value) -> Result<(), Error> {
+    for guild in guilds_mut(backup)? {
+        set_field(guild, "regex_mutators", Vec::<RegexMutator>::new())?;
+    }
+
+    Ok(())
+}
+
+// Pronoun sets for Misgendering
+fn v12_to_v13(backup: &mut Value) -> Result<(), Error> {
+    for guild in guilds_mut(backup)? {
+        set_field(guild, "pronoun_sets", PronounSet::default_sets())?;
+    }
+
+    Ok(())
+}
+
+// Who each reply came from
+fn v13_to_v14(backup: &mut Value) -> Result<(), Error> {
+    for guild in guilds_mut(backup)? {
+        set_field(guild, "reply_sources", Vec::<(MessageId, Vec<UserId>)>::new())?;
+    }
+
+    Ok(())
+}
+
+// Only custom emojis like before
+fn v14_to_v15(backup: &mut Value) -> Result<(), Error> {
+    for guild in guilds_mut(backup)? {
+        set_field(guild, "unicode_emojis", false)?;
+    }
+
+    Ok(())
+}
+
+// No emoji use seen yet
+fn v15_to_v16(backup: &mut Value) -> Result<(), Error> {
+    for guild in guilds_mut(backup)? {
+        set_field(guild, "reaction_stats", ReactionStats::default())?;
+    }
+
+    Ok(())
+}
+
+// Backups without an envelope are either 2.0.0 or 3.0.0, only 3.0.0 guilds have mutators
+fn legacy_version(backup: &Value) -> u32 {
+    let is_v3 = field(backup, "guilds_values")
+        .and_then(|g| g.as_array())
+        .and_then(|g| g.first())
+        .is_none_or(|g| field(g, "allowed_mutators").is_some());
+
+    if is_v3 { 3 } else { 2 }
+}
+
+// MIGRATION HELPERS
+fn field<'a>(map: &'a Value, key: &str) -> Option<&'a Value> {
+    map.as_map()?
+        .iter()
+        .find(|(k, _)| k.as_text() == Some(key))
+        .map(|(_, v)| v)
+}
+
+fn field_mut<'a>(map: &'a mut Value, key: &str) -> Option<&'a mut Value> {
+    map.as_map_mut()?
+        .iter_mut()
+        .find(|(k, _)| k.as_text() == Some(key))
+        .map(|(_, v)| v)
+}
+
+fn guilds_mut(backup: &mut Value) -> Result<&mut Vec<Value>, Error> {
+    match field_mut(backup, "guilds_values").and_then(|g| g.as_array_mut()) {
+        None => Err("backup has no guilds_values".into()),
+        Some(g) => Ok(g),
+    }
+}
+
+fn remove_field(map: &mut Value, key: &str) -> Option<Value> {
+    let map = map.as_map_mut()?;
+    let idx = map.iter().position(|(k, _)| k.as_text() == Some(key))?;
+
+    Some(map.remove(idx).1)
+}
+
+// Sets the field to the given value, adding it if it's missing
+fn set_field<T: Serialize>(map: &mut Value, key: &str, value: T) -> Result<(), Error> {
+    let value = Value::serialized(&value)?;
+
+    if let Some(v) = field_mut(map, key) {
+        *v = value;
+        return Ok(());
+    }
+
+    match map.as_map_mut() {
+        None => Err(format!("expected a map to put {} in", key).into()),
+        Some(m) => {
+            m.push((Value::Text(key.to_string()), value));
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{SclunerMessage, SclunerRng};
+    use rand::SeedableRng;
+
+    const GUILD: GuildId = GuildId::new(3);
+    const USER: UserId = UserId::new(2);
+
+    // How 2.0.0 wrote guilds
+    #[derive(Serialize)]
+    struct GuildV2 {
+        guild_id: GuildId,
+        messages: Vec<SclunerMessage>,
+        asleep: bool,
+        min_proc: u32,
+        max_proc: u32,
+        proc_out_of: u32,
+        proc: u32,
+    }
+
+    #[derive(Serialize)]
+    struct GuildV3 {
+        #[serde(flatten)]
+        v2: GuildV2,
+        allowed_mutators: Vec<DefinedMutators>,
+    }
+
+    // Neither had an envelope, both had a global modlist
+    #[derive(Serialize)]
+    struct LegacyBackup<G> {
+        guilds_keys: Vec<GuildId>,
+        guilds_values: Vec<G>,
+        whitelist: Vec<UserId>,
+        blacklist: Vec<UserId>,
+        modlist: Vec<UserId>,
+    }
+
+    fn guild_v2() -> GuildV2 {
+        GuildV2 {
+            guild_id: GUILD,
+            messages: vec![SclunerMessage { user_id: USER, content: "hello there".to_string() }],
+            asleep: true,
+            min_proc: 1,
+            max_proc: 5,
+            proc_out_of: 100,
+            proc: 3,
+        }
+    }
+
+    fn legacy_bytes<G: Serialize>(guild: G) -> Vec<u8> {
+        let backup = LegacyBackup {
+            guilds_keys: vec![GUILD],
+            guilds_values: vec![guild],
+            whitelist: vec![USER],
+            blacklist: Vec::new(),
+            modlist: vec![USER],
+        };
+
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&backup, &mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn migrates_v2_backups() {
+        let backup = SclunerBackup::from_bytes(&legacy_bytes(guild_v2())).unwrap();
+        let guild = &backup.guilds_values[0];
+
+        assert_eq!(backup.guilds_keys, [GUILD]);
+        assert_eq!(backup.whitelist, [USER]);
+        assert_eq!(guild.messages[0].content, "hello there");
+        assert!(guild.asleep);
+        assert_eq!((guild.min_proc, guild.max_proc, guild.proc_out_of, guild.proc), (1, 5, 100, 3));
+        assert_eq!(guild.allowed_mutators, DefinedMutators::default_allowed());
+        // The global modlist moderates every guild
+        assert_eq!(guild.moderators, [USER]);
+        assert!(guild.reply_mode == ReplyMode::Replay);
+        assert_eq!(guild.pronoun_sets, PronounSet::default_sets());
+        assert!(guild.learn_by_default && guild.speak_by_default);
+        assert!(!guild.unicode_emojis);
+    }
+
+    #[test]
+    fn migrates_v3_backups() {
+        let bytes = legacy_bytes(GuildV3 { v2: guild_v2(), allowed_mutators: vec![DefinedMutators::AppendEmote] });
+        let backup = SclunerBackup::from_bytes(&bytes).unwrap();
+
+        // Kept instead of getting the 2.0.0 defaults
+        assert_eq!(backup.guilds_values[0].allowed_mutators, [DefinedMutators::AppendEmote]);
+        assert_eq!(backup.guilds_values[0].moderators, [USER]);
+    }
+
+    #[test]
+    fn round_trips_current_backups() {
+        // One entry per map, HashMaps don't serialize in a fixed order
+        let mut guild = SclunerGuild::new(GUILD, SclunerRng::seed_from_u64(0));
+        guild.remember(SclunerMessage { user_id: USER, content: "hello there".to_string() });
+        guild.channels.insert(ChannelId::new(4), SclunerChannel { learn: Some(false), ..Default::default() });
+        guild.quiet_hours.push(QuietHours { start: 22 * 60, end: 7 * 60 });
+        guild.mutator_chances.insert(DefinedMutators::Typos, (1, 3));
+        guild.regex_mutators.push(RegexMutator::new("a".to_string(), "a+".to_string(), "b".to_string(), 1, 2).unwrap());
+        guild.reply_sources.push_back((MessageId::new(5), vec![USER]));
+        guild.reaction_stats.record(&ReactionType::Unicode("🐸".to_string()), "pond");
+        guild.moderators.push(USER);
+        guild.reply_mode = ReplyMode::Markov;
+
+        let backup = SclunerBackup::new(&HashMap::from([(GUILD, guild)]), &[USER], &[UserId::new(6)]);
+        let loaded = SclunerBackup::from_bytes(&backup.to_bytes().unwrap()).unwrap();
+
+        assert_eq!(Value::serialized(&loaded).unwrap(), Value::serialized(&backup).unwrap());
+    }
+
+    #[test]
+    fn rejects_newer_backups() {
+        let envelope = SclunerBackupEnvelope {
+            version: BACKUP_VERSION + 1,
+            backup: Value::serialized(&SclunerBackup::new(&HashMap::new(), &[], &[])).unwrap(),
+        };
+        let mut bytes = Vec::new();
+        ciborium::into_writer(&envelope, &mut bytes).unwrap();
+
+        let error = SclunerBackup::from_bytes(&bytes).err().unwrap();
+        assert!(error.to_string().contains("newer"), "{}", error);
+    }
+}
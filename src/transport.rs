@@ -0,0 +1,69 @@
+use std::time::Duration;
+
+use poise::serenity_prelude as serenity;
+use poise::serenity_prelude::*;
+
+use crate::Error;
+
+// Keeps the typing indicator going until dropped
+pub type TypingGuard = Box<dyn Send>;
+
+// Everything the bot does to the outside world goes through here
+#[async_trait]
+pub trait Transport: Send + Sync {
+    fn current_user_id(&self) -> UserId;
+    fn start_typing(&self, channel_id: ChannelId) -> TypingGuard;
+    async fn wait(&self, duration: Duration);
+
+    async fn send_message(&self, channel_id: ChannelId, content: String) -> Result<MessageId, Error>;
+    async fn reply(&self, channel_id: ChannelId, message_id: MessageId, content: String) -> Result<MessageId, Error>;
+    async fn react(&self, channel_id: ChannelId, message_id: MessageId, reaction: ReactionType) -> Result<(), Error>;
+    async fn guild_emojis(&self, guild_id: GuildId) -> Result<Vec<ReactionType>, Error>;
+    async fn last_message_id(&self, channel_id: ChannelId) -> Result<Option<MessageId>, Error>;
+}
+
+// DEFINITIONS
+pub struct SerenityTransport<'a> {
+    pub ctx: &'a serenity::Context,
+}
+
+// IMPLEMENTATIONS
+#[async_trait] // Talks to discord
+impl Transport for SerenityTransport<'_> {
+    fn current_user_id(&self) -> UserId {
+        self.ctx.cache.current_user().id
+    }
+
+    fn start_typing(&self, channel_id: ChannelId) -> TypingGuard {
+        Box::new(channel_id.start_typing(&self.ctx.http))
+    }
+
+    async fn wait(&self, duration: Duration) {
+        async_std::task::sleep(duration).await;
+    }
+
+    async fn send_message(&self, channel_id: ChannelId, content: String) -> Result<MessageId, Error> {
+        Ok(channel_id.say(self.ctx.http(), content).await?.id)
+    }
+
+    async fn reply(&self, channel_id: ChannelId, message_id: MessageId, content: String) -> Result<MessageId, Error> {
+        let reply = CreateMessage::new()
+            .content(content)
+            .reference_message((channel_id, message_id));
+
+        Ok(channel_id.send_message(self.ctx.http(), reply).await?.id)
+    }
+
+    async fn react(&self, channel_id: ChannelId, message_id: MessageId, reaction: ReactionType) -> Result<(), Error> {
+        self.ctx.http().create_reaction(channel_id, message_id, &reaction).await?;
+        Ok(())
+    }
+
+    async fn guild_emojis(&self, guild_id: GuildId) -> Result<Vec<ReactionType>, Error> {
+        Ok(guild_id.emojis(self.ctx.http()).await?.into_iter().map(ReactionType::from).collect())
+    }
+
+    async fn last_message_id(&self, channel_id: ChannelId) -> Result<Option<MessageId>, Error> {
+        Ok(channel_id.messages(self.ctx.http(), GetMessages::new().limit(1)).await?.pop().map(|m| m.id))
+    }
+}
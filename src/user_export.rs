@@ -0,0 +1,116 @@
+use poise::serenity_prelude::*;
+use serde::Serialize;
+
+use crate::SclunerInstance;
+
+// Everything the bot holds about a single user
+#[derive(Serialize)]
+pub struct SclunerUserExport {
+    pub user_id: UserId,
+    pub whitelisted: bool,
+    pub blacklisted: bool,
+    pub owner: bool,
+    pub guilds: Vec<SclunerGuildExport>,
+}
+
+#[derive(Serialize)]
+pub struct SclunerGuildExport {
+    pub guild_id: GuildId,
+    pub moderator: bool,
+    pub blacklisted: bool,
+    pub messages: Vec<String>,
+    // Replies made from their memories
+    pub replies: Vec<MessageId>,
+}
+
+impl SclunerUserExport {
+    pub fn new(instance: &SclunerInstance, user_id: UserId) -> Self {
+        let mut guilds: Vec<SclunerGuildExport> = instance
+            .guilds
+            .values()
+            .map(|g| SclunerGuildExport {
+                guild_id: g.guild_id,
+                moderator: g.moderators.contains(&user_id),
+                blacklisted: g.blacklist.contains(&user_id),
+                messages: g
+                    .messages
+                    .iter()
+                    .filter(|m| m.user_id == user_id)
+                    .map(|m| m.content.clone())
+                    .collect(),
+                replies: g
+                    .reply_sources
+                    .iter()
+                    .filter(|(_, sources)| sources.contains(&user_id))
+                    .map(|(id, _)| *id)
+                    .collect(),
+            })
+            .filter(|g| g.moderator || g.blacklisted || !g.messages.is_empty() || !g.replies.is_empty())
+            .collect();
+
+        guilds.sort_by_key(|g| g.guild_id);
+
+        Self {
+            user_id,
+            whitelisted: instance.whitelist.contains(&user_id),
+            blacklisted: instance.blacklist.contains(&user_id),
+            owner: instance.owners.contains(&user_id),
+            guilds,
+        }
+    }
+
+    pub fn message_count(&self) -> usize {
+        self.guilds.iter().map(|g| g.messages.len()).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::backup_storage::LocalDirStorage;
+    use crate::SclunerMessage;
+
+    const ALICE: UserId = UserId::new(2);
+    const BOB: UserId = UserId::new(3);
+
+    #[test]
+    fn exports_only_the_callers_data() {
+        let mut instance = SclunerInstance::new(Arc::new(LocalDirStorage { dir: PathBuf::new() }), Vec::new());
+        instance.whitelist.push(ALICE);
+        instance.blacklist.push(BOB);
+
+        let shared = instance.guild_mut(GuildId::new(10));
+        shared.remember(SclunerMessage { user_id: ALICE, content: "alice here".to_string() });
+        shared.remember(SclunerMessage { user_id: BOB, content: "bob here".to_string() });
+        shared.moderators.push(ALICE);
+        shared.blacklist.push(BOB);
+        shared.reply_sources.push_back((MessageId::new(20), vec![ALICE, BOB]));
+        shared.reply_sources.push_back((MessageId::new(21), vec![BOB]));
+
+        let bobs = instance.guild_mut(GuildId::new(11));
+        bobs.remember(SclunerMessage { user_id: BOB, content: "only bob".to_string() });
+        bobs.moderators.push(BOB);
+
+        let export = SclunerUserExport::new(&instance, ALICE);
+
+        assert!(export.whitelisted && !export.blacklisted && !export.owner);
+        assert_eq!(export.guilds.len(), 1);
+        let guild = &export.guilds[0];
+        assert_eq!(guild.guild_id, GuildId::new(10));
+        assert!(guild.moderator && !guild.blacklisted);
+        assert_eq!(guild.messages, ["alice here"]);
+        assert_eq!(guild.replies, [MessageId::new(20)]);
+        assert_eq!(export.message_count(), 1);
+
+        let export = SclunerUserExport::new(&instance, BOB);
+        assert!(export.blacklisted);
+        assert_eq!(export.guilds.len(), 2);
+        assert!(export.guilds[0].blacklisted && !export.guilds[0].moderator);
+        assert_eq!(export.guilds[0].replies, [MessageId::new(20), MessageId::new(21)]);
+        assert!(export.guilds[1].moderator);
+        assert_eq!(export.message_count(), 2);
+    }
+}
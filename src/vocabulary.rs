@@ -0,0 +1,150 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::SclunerMessage;
+
+// Stands in for the neighbour of the first and last words, normalized words are never empty
+const BOUNDARY: &str = "";
+// Shorter words are mostly glue like "a" or "to" and not worth swapping
+const MIN_WORD_LEN: usize = 3;
+
+type Counts = BTreeMap<String, u32>;
+
+// Which words show up next to which, so words used the same way can stand in for each other
+#[derive(Clone, Default)]
+pub struct Vocabulary {
+    // (previous word, next word) -> words between them
+    between: HashMap<(String, String), Counts>,
+    after: HashMap<String, Counts>,
+    before: HashMap<String, Counts>,
+}
+
+// Lowercase with the surrounding punctuation gone, "Cat's," -> "cat's"
+pub fn normalize(token: &str) -> String {
+    token.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase()
+}
+
+pub fn is_swappable(word: &str) -> bool {
+    word.chars().count() >= MIN_WORD_LEN && word.chars().all(|c| c.is_alphabetic() || c == '\'')
+}
+
+fn add(counts: &mut Counts, word: &str) {
+    *counts.entry(word.to_string()).or_insert(0) += 1;
+}
+
+// Returns whether counts is now empty
+fn remove(counts: &mut Counts, word: &str) -> bool {
+    if let Some(count) = counts.get_mut(word) {
+        *count -= 1;
+        if *count == 0 {
+            counts.remove(word);
+        }
+    }
+
+    counts.is_empty()
+}
+
+impl Vocabulary {
+    pub fn build(messages: &[SclunerMessage]) -> Self {
+        let mut vocabulary = Self::default();
+
+        for message in messages {
+            vocabulary.learn(&message.content);
+        }
+
+        vocabulary
+    }
+
+    // Every (previous, word, next) where word can be swapped
+    fn contexts(content: &str) -> Vec<(String, String, String)> {
+        let mut words = vec![BOUNDARY.to_string()];
+        words.extend(content.split_whitespace().map(normalize).filter(|w| !w.is_empty()));
+        words.push(BOUNDARY.to_string());
+
+        words
+            .windows(3)
+            .filter(|w| is_swappable(&w[1]))
+            .map(|w| (w[0].clone(), w[1].clone(), w[2].clone()))
+            .collect()
+    }
+
+    pub fn learn(&mut self, content: &str) {
+        for (previous, word, next) in Self::contexts(content) {
+            add(self.between.entry((previous.clone(), next.clone())).or_default(), &word);
+            add(self.after.entry(previous).or_default(), &word);
+            add(self.before.entry(next).or_default(), &word);
+        }
+    }
+
+    pub fn forget(&mut self, content: &str) {
+        for (previous, word, next) in Self::contexts(content) {
+            let key = (previous, next);
+
+            if self.between.get_mut(&key).is_some_and(|c| remove(c, &word)) {
+                self.between.remove(&key);
+            }
+            if self.after.get_mut(&key.0).is_some_and(|c| remove(c, &word)) {
+                self.after.remove(&key.0);
+            }
+            if self.before.get_mut(&key.1).is_some_and(|c| remove(c, &word)) {
+                self.before.remove(&key.1);
+            }
+        }
+    }
+
+    // Words seen in the same spot as word with how often, the closest match that has any wins
+    // Only being at the start or end of a message says too little to go on by itself
+    pub fn candidates(&self, previous: &str, word: &str, next: &str) -> Vec<(&str, u32)> {
+        let pools = [
+            self.between.get(&(previous.to_string(), next.to_string())),
+            self.after.get(previous).filter(|_| previous != BOUNDARY),
+            self.before.get(next).filter(|_| next != BOUNDARY),
+        ];
+
+        pools
+            .into_iter()
+            .flatten()
+            .map(|counts| {
+                counts
+                    .iter()
+                    .filter(|(w, _)| w.as_str() != word)
+                    .map(|(w, c)| (w.as_str(), *c))
+                    .collect::<Vec<_>>()
+            })
+            .find(|candidates| !candidates.is_empty())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_words_used_the_same_way() {
+        let vocabulary = Vocabulary::build(&[
+            SclunerMessage { user_id: 1.into(), content: "i love my cat".to_string() },
+            SclunerMessage { user_id: 1.into(), content: "i love my dog".to_string() },
+            SclunerMessage { user_id: 1.into(), content: "the dog barked".to_string() },
+        ]);
+
+        assert_eq!(vocabulary.candidates("my", "cat", ""), [("dog", 1)]);
+        // dog is the only word seen around there
+        assert_eq!(vocabulary.candidates("the", "dog", "barked"), Vec::<(&str, u32)>::new());
+        assert_eq!(vocabulary.candidates("i", "love", "my"), Vec::<(&str, u32)>::new());
+    }
+
+    #[test]
+    fn forgetting_undoes_learning() {
+        let mut vocabulary = Vocabulary::build(&[
+            SclunerMessage { user_id: 1.into(), content: "Cats, dogs and birds!".to_string() },
+        ]);
+
+        vocabulary.learn("the birds sing");
+        vocabulary.forget("the birds sing");
+        vocabulary.forget("Cats, dogs and birds!");
+
+        assert!(vocabulary.between.is_empty());
+        assert!(vocabulary.after.is_empty());
+        assert!(vocabulary.before.is_empty());
+    }
+}